@@ -0,0 +1,78 @@
+/// Hosts of video-conference providers we recognize in free text
+const CONFERENCE_HOSTS: [&str; 6] = [
+    "zoom.us",
+    "teams.microsoft.com",
+    "teams.live.com",
+    "meet.google.com",
+    "meet.jit.si",
+    "jitsi.",
+];
+
+/// Check if a URL points to a known video-conference provider
+pub fn is_conference_url(url: &str) -> bool {
+    let host = match url_host(url) {
+        Some(host) => host.to_lowercase(),
+        None => return false,
+    };
+
+    CONFERENCE_HOSTS.iter().any(|known| {
+        if known.ends_with('.') {
+            // Prefix match, e.g. self-hosted "jitsi.example.org"
+            host.starts_with(known)
+        } else {
+            host == *known || host.ends_with(&format!(".{}", known))
+        }
+    })
+}
+
+/// Find the first conference link in free text (LOCATION or DESCRIPTION)
+pub fn find_conference_url(text: &str) -> Option<String> {
+    extract_urls(text)
+        .into_iter()
+        .find(|url| is_conference_url(url))
+}
+
+/// Check if a value is a web link (http or https)
+pub fn is_web_url(url: &str) -> bool {
+    let lower = url.to_lowercase();
+    lower.starts_with("https://") || lower.starts_with("http://")
+}
+
+/// Extract all http(s) URLs from free text
+fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = find_url_start(rest) {
+        let candidate = &rest[start..];
+        // URL ends at whitespace, quotes or angle brackets (descriptions may contain HTML)
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+            .unwrap_or(candidate.len());
+        let url = candidate[..end].trim_end_matches(['.', ',', ';', ')', ']']);
+
+        if url.len() > "https://".len() {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end.max(1)..];
+    }
+
+    urls
+}
+
+fn find_url_start(text: &str) -> Option<usize> {
+    let https = text.find("https://");
+    let http = text.find("http://");
+    match (https, http) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn url_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    // Drop user info and port
+    let host = authority.rsplit('@').next()?;
+    Some(host.split(':').next().unwrap_or(host))
+}
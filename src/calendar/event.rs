@@ -1,10 +1,14 @@
 use std::fs;
-use std::path::Path;
 use serde::{Deserialize, Serialize};
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use super::storage;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Event {
+    /// UID from the feed (shared by all occurrences of a recurring event)
+    #[serde(default)]
+    pub uid: String,
     pub title: String,
     pub start_date: String,
     pub end_date: String,
@@ -12,39 +16,145 @@ pub struct Event {
     pub description: String,
     #[serde(default)]
     pub calendar_id: String,
+    #[serde(default)]
+    pub organizer: Option<Attendee>,
+    #[serde(default)]
+    pub attendees: Vec<Attendee>,
+    /// Video-conference join link (empty if none)
+    #[serde(default)]
+    pub conference_url: String,
+    /// CATEGORIES, used as tags
+    #[serde(default)]
+    pub categories: Vec<String>,
 }
 
-const STORAGE_PATH: &str = "data/events.json";
-const TIMESTAMP_PATH: &str = "data/last_update.txt";
+/// An ORGANIZER or ATTENDEE of an event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attendee {
+    pub email: String,
+    /// Common name (CN), may be empty
+    #[serde(default)]
+    pub name: String,
+    /// Participation status (PARTSTAT): NEEDS-ACTION, ACCEPTED, DECLINED, TENTATIVE, ...
+    #[serde(default)]
+    pub status: String,
+    /// ROLE: CHAIR, REQ-PARTICIPANT, OPT-PARTICIPANT, NON-PARTICIPANT
+    #[serde(default)]
+    pub role: String,
+    #[serde(default)]
+    pub rsvp: bool,
+}
 
-/// Load events from JSON file
-/// Returns empty vec if file doesn't exist or on error
-pub fn load_events() -> Vec<Event> {
-    if !Path::new(STORAGE_PATH).exists() {
-        return Vec::new();
+impl Attendee {
+    /// Name to show in the agenda (CN, falling back to the email address)
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            &self.email
+        } else {
+            &self.name
+        }
     }
 
-    fs::read_to_string(STORAGE_PATH)
-        .ok()
-        .and_then(|json| serde_json::from_str(&json).ok())
-        .unwrap_or_default()
+    pub fn has_email(&self, emails: &[String]) -> bool {
+        emails.iter().any(|email| email.eq_ignore_ascii_case(&self.email))
+    }
 }
 
-/// Save events to JSON file
-pub fn save_events(events: &[Event]) -> Result<(), Box<dyn std::error::Error>> {
-    fs::create_dir_all("data")?;
-    let json = serde_json::to_string_pretty(events)?;
-    fs::write(STORAGE_PATH, json)?;
+impl Event {
+    /// Stable id for the API: hash of calendar, UID and start
+    /// (falls back to the title for events without UID)
+    pub fn id(&self) -> String {
+        let key = if self.uid.is_empty() { &self.title } else { &self.uid };
+        // FNV-1a, 64 bit
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in [self.calendar_id.as_str(), key, self.start_date.as_str()].join("\n").bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// My own participation status, if one of my email addresses is invited
+    pub fn my_status(&self, my_emails: &[String]) -> Option<&str> {
+        self.attendees
+            .iter()
+            .find(|attendee| attendee.has_email(my_emails))
+            .map(|attendee| attendee.status.as_str())
+    }
+
+    /// Check if the event has a category (case-insensitive)
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.categories.iter().any(|category| category.eq_ignore_ascii_case(tag))
+    }
+
+    /// Start time in the given timezone (None for all-day or unparsable dates)
+    pub fn start_time(&self, tz: Tz) -> Option<DateTime<Tz>> {
+        parse_event_time(&self.start_date, tz)
+    }
+
+    /// End time in the given timezone (None for all-day or unparsable dates)
+    pub fn end_time(&self, tz: Tz) -> Option<DateTime<Tz>> {
+        parse_event_time(&self.end_date, tz)
+    }
 
-    // Update timestamp
-    let timestamp = Utc::now().to_rfc3339();
-    fs::write(TIMESTAMP_PATH, timestamp)?;
+    /// All-day events only have a date (format: YYYY-MM-DD)
+    pub fn is_all_day(&self) -> bool {
+        NaiveDate::parse_from_str(&self.start_date, "%Y-%m-%d").is_ok()
+    }
+
+    /// Start and end in the given timezone, all-day events spanning whole days
+    pub fn time_range(&self, tz: Tz) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        if self.is_all_day() {
+            let start = parse_event_date(&self.start_date, tz)?;
+            // DTEND of all-day events is exclusive; a missing end means one day
+            let end = parse_event_date(&self.end_date, tz).unwrap_or(start + chrono::Duration::days(1));
+            return Some((start, end));
+        }
+        Some((self.start_time(tz)?, self.end_time(tz)?))
+    }
+
+    /// Other attendees (excluding me and the organizer)
+    pub fn other_attendees<'a>(&'a self, my_emails: &'a [String]) -> impl Iterator<Item = &'a Attendee> {
+        let organizer_email = self.organizer.as_ref().map(|o| o.email.as_str());
+        self.attendees.iter().filter(move |attendee| {
+            !attendee.has_email(my_emails)
+                && Some(attendee.email.as_str()) != organizer_email
+                && attendee.role != "NON-PARTICIPANT"
+        })
+    }
+}
+
+/// Parse a stored date (format: YYYY-MM-DD HH:MM:SS) as local time in `tz`
+fn parse_event_time(date: &str, tz: Tz) -> Option<DateTime<Tz>> {
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").ok()?;
+    tz.from_local_datetime(&naive).earliest()
+}
 
-    Ok(())
+/// Parse a stored all-day date (format: YYYY-MM-DD) as midnight in `tz`
+fn parse_event_date(date: &str, tz: Tz) -> Option<DateTime<Tz>> {
+    let naive = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?;
+    tz.from_local_datetime(&naive).earliest()
+}
+
+const STORAGE_PATH: &str = "data/events.json";
+
+/// Where the sync time was kept before events.json had a header
+const LEGACY_TIMESTAMP_PATH: &str = "data/last_update.txt";
+
+/// Load events from JSON file
+/// Returns empty vec if neither the file nor its backup can be read
+pub fn load_events() -> Vec<Event> {
+    storage::load(STORAGE_PATH)
+}
+
+/// Save events to JSON file (atomically, with the sync time in its header)
+pub fn save_events(events: &[Event]) -> Result<(), Box<dyn std::error::Error>> {
+    storage::save(STORAGE_PATH, events)
 }
 
 /// Get the last update timestamp
 pub fn get_last_update() -> String {
-    fs::read_to_string(TIMESTAMP_PATH)
-        .unwrap_or_else(|_| Utc::now().to_rfc3339())
+    storage::saved_at(STORAGE_PATH)
+        .or_else(|| fs::read_to_string(LEGACY_TIMESTAMP_PATH).ok())
+        .unwrap_or_else(|| Utc::now().to_rfc3339())
 }
@@ -1,31 +1,60 @@
-use super::event::Event;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use super::conference::{find_conference_url, is_conference_url, is_web_url};
+use super::event::{Attendee, Event};
+use super::info::{normalize_color, CalendarInfo};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use chrono_tz::Tz;
 
 pub fn parse_ics(content: &str, timezone: &str, calendar_id: &str) -> Vec<Event> {
     let mut events = Vec::new();
     let mut current_event: Option<EventBuilder> = None;
+    // Depth of sub-components (e.g. VALARM) inside the current VEVENT
+    let mut nested_depth: usize = 0;
 
     // Parse timezone
     let tz: Tz = timezone.parse().unwrap_or(chrono_tz::UTC);
 
-    for line in content.lines() {
+    for line in unfold_lines(content) {
         let line = line.trim();
 
         if line == "BEGIN:VEVENT" {
             current_event = Some(EventBuilder::new(calendar_id.to_string()));
+            nested_depth = 0;
         } else if line == "END:VEVENT" {
             if let Some(builder) = current_event.take() {
                 events.push(builder.build());
             }
         } else if let Some(ref mut builder) = current_event {
-            if let Some((key, value)) = line.split_once(':') {
-                match key {
-                    "SUMMARY" => builder.title = value.to_string(),
-                    "DTSTART" => builder.start_date = parse_ics_date(value, tz),
-                    "DTEND" => builder.end_date = parse_ics_date(value, tz),
-                    "LOCATION" => builder.location = value.to_string(),
-                    "DESCRIPTION" => builder.description = unescape_ics_text(value),
+            // Skip properties of nested components such as alarms
+            if line.starts_with("BEGIN:") {
+                nested_depth += 1;
+                continue;
+            }
+            if line.starts_with("END:") {
+                // A stray END: must not hide the properties that follow
+                nested_depth = nested_depth.saturating_sub(1);
+                continue;
+            }
+            if nested_depth > 0 {
+                continue;
+            }
+
+            if let Some(property) = Property::parse(line) {
+                match property.name.as_str() {
+                    "UID" => builder.uid = property.value.clone(),
+                    "SUMMARY" => builder.title = unescape_ics_text(&property.value),
+                    "DTSTART" => builder.start_date = parse_ics_date(&property.value, property.param("TZID"), tz),
+                    "DTEND" => builder.end_date = parse_ics_date(&property.value, property.param("TZID"), tz),
+                    "LOCATION" => builder.location = unescape_ics_text(&property.value),
+                    "DESCRIPTION" => builder.description = unescape_ics_text(&property.value),
+                    "ORGANIZER" => builder.organizer = Some(parse_attendee(&property)),
+                    "ATTENDEE" => builder.attendees.push(parse_attendee(&property)),
+                    // RFC 7986, may also be a dial-in (tel:) - keep only web links
+                    "CONFERENCE" if builder.conference_url.is_none() && is_web_url(&property.value) => {
+                        builder.conference_url = Some(property.value.clone());
+                    }
+                    "X-GOOGLE-CONFERENCE" => builder.google_conference_url = Some(property.value.clone()),
+                    "URL" => builder.url = Some(property.value.clone()),
+                    "CATEGORIES" => builder.categories.extend(split_ics_list(&property.value)),
                     _ => {}
                 }
             }
@@ -35,41 +64,201 @@ pub fn parse_ics(content: &str, timezone: &str, calendar_id: &str) -> Vec<Event>
     events
 }
 
+/// Read calendar-level metadata (name and color) from a feed
+pub fn parse_calendar_info(content: &str, calendar_id: &str) -> CalendarInfo {
+    let mut name = None;
+    let mut wr_name = None;
+    let mut color = None;
+    let mut apple_color = None;
+    // Component nesting: 1 = directly inside VCALENDAR
+    let mut depth: usize = 0;
+
+    for line in unfold_lines(content) {
+        let line = line.trim();
+
+        if line.starts_with("BEGIN:") {
+            depth += 1;
+        } else if line.starts_with("END:") {
+            depth = depth.saturating_sub(1);
+        } else if depth == 1 {
+            if let Some(property) = Property::parse(line) {
+                let value = unescape_ics_text(&property.value);
+                match property.name.as_str() {
+                    "NAME" => name = Some(value),
+                    "X-WR-CALNAME" => wr_name = Some(value),
+                    "COLOR" => color = normalize_color(&value),
+                    "X-APPLE-CALENDAR-COLOR" => apple_color = normalize_color(&value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    CalendarInfo {
+        id: calendar_id.to_string(),
+        // Prefer the RFC 7986 properties over the vendor extensions
+        name: name.or(wr_name).unwrap_or_default(),
+        color: color.or(apple_color).unwrap_or_default(),
+    }
+}
+
 struct EventBuilder {
+    uid: String,
     title: String,
     start_date: String,
     end_date: String,
     location: String,
     description: String,
     calendar_id: String,
+    organizer: Option<Attendee>,
+    attendees: Vec<Attendee>,
+    conference_url: Option<String>,
+    google_conference_url: Option<String>,
+    url: Option<String>,
+    categories: Vec<String>,
 }
 
 impl EventBuilder {
     fn new(calendar_id: String) -> Self {
         EventBuilder {
+            uid: String::new(),
             title: String::new(),
             start_date: String::new(),
             end_date: String::new(),
             location: String::new(),
             description: String::new(),
             calendar_id,
+            organizer: None,
+            attendees: Vec::new(),
+            conference_url: None,
+            google_conference_url: None,
+            url: None,
+            categories: Vec::new(),
         }
     }
 
     fn build(self) -> Event {
+        // Pick the join link: explicit conference properties first, then known providers in free text
+        let conference_url = self
+            .conference_url
+            .or(self.google_conference_url.filter(|url| is_web_url(url)))
+            .or(self.url.filter(|url| is_conference_url(url)))
+            .or_else(|| find_conference_url(&self.location))
+            .or_else(|| find_conference_url(&self.description))
+            .unwrap_or_default();
+
         Event {
+            uid: self.uid,
             title: self.title,
             start_date: self.start_date,
             end_date: self.end_date,
             location: self.location,
             description: self.description,
             calendar_id: self.calendar_id,
+            organizer: self.organizer,
+            attendees: self.attendees,
+            conference_url,
+            categories: self.categories,
         }
     }
 }
 
-fn parse_ics_date(date_str: &str, target_tz: Tz) -> String {
-    // ICS date format: 20250115T100000Z (UTC)
+/// A single content line split into name, parameters and value
+/// e.g. `ATTENDEE;CN="Doe, Jane";PARTSTAT=ACCEPTED:mailto:jane@example.com`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Property> {
+        let mut name = String::new();
+        let mut params = Vec::new();
+        let mut current = String::new();
+        let mut param_name: Option<String> = None;
+        let mut in_quotes = false;
+
+        for (i, c) in line.char_indices() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ';' | ':' if !in_quotes => {
+                    // Finish the name or the parameter we were reading
+                    match param_name.take() {
+                        Some(key) => params.push((key, std::mem::take(&mut current))),
+                        None if name.is_empty() => name = std::mem::take(&mut current),
+                        None => current.clear(),
+                    }
+
+                    if c == ':' {
+                        return Some(Property {
+                            name: name.to_uppercase(),
+                            params,
+                            value: line[i + 1..].to_string(),
+                        });
+                    }
+                }
+                '=' if !in_quotes && param_name.is_none() && !name.is_empty() => {
+                    param_name = Some(std::mem::take(&mut current).to_uppercase());
+                }
+                _ => current.push(c),
+            }
+        }
+
+        None
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Join folded lines (RFC 5545: continuation lines start with a space or tab)
+fn unfold_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+
+    lines
+}
+
+fn parse_attendee(property: &Property) -> Attendee {
+    // Value is a cal-address, usually "mailto:someone@example.com"
+    let value = property.value.trim();
+    let email = if value.len() >= 7 && value[..7].eq_ignore_ascii_case("mailto:") {
+        &value[7..]
+    } else {
+        value
+    };
+
+    Attendee {
+        email: email.to_string(),
+        name: property.param("CN").unwrap_or("").to_string(),
+        status: property.param("PARTSTAT").unwrap_or("NEEDS-ACTION").to_uppercase(),
+        role: property.param("ROLE").unwrap_or("REQ-PARTICIPANT").to_uppercase(),
+        rsvp: property
+            .param("RSVP")
+            .map(|v| v.eq_ignore_ascii_case("TRUE"))
+            .unwrap_or(false),
+    }
+}
+
+fn parse_ics_date(date_str: &str, tzid: Option<&str>, target_tz: Tz) -> String {
+    // ICS date formats:
+    //   20250115T100000Z                    (UTC)
+    //   TZID=Europe/Berlin:20250115T100000  (local time in TZID)
     // Parse it and convert to target timezone
 
     if date_str.len() >= 15 && date_str.contains('T') {
@@ -78,8 +267,20 @@ fn parse_ics_date(date_str: &str, target_tz: Tz) -> String {
 
         // Parse the datetime string
         if let Ok(naive_dt) = NaiveDateTime::parse_from_str(clean_date, "%Y%m%dT%H%M%S") {
-            // Assume UTC as source timezone
-            let utc_dt: DateTime<Utc> = DateTime::from_naive_utc_and_offset(naive_dt, Utc);
+            // Use TZID as source timezone when given, otherwise assume UTC
+            let source_tz: Option<Tz> = if date_str.ends_with('Z') {
+                None
+            } else {
+                tzid.and_then(|id| id.parse().ok())
+            };
+
+            let utc_dt: DateTime<Utc> = match source_tz {
+                Some(source_tz) => match source_tz.from_local_datetime(&naive_dt).earliest() {
+                    Some(local_dt) => local_dt.with_timezone(&Utc),
+                    None => DateTime::from_naive_utc_and_offset(naive_dt, Utc),
+                },
+                None => DateTime::from_naive_utc_and_offset(naive_dt, Utc),
+            };
 
             // Convert to target timezone
             let target_dt = utc_dt.with_timezone(&target_tz);
@@ -102,8 +303,53 @@ fn parse_ics_date(date_str: &str, target_tz: Tz) -> String {
     }
 }
 
+/// Split a comma-separated value list (an escaped `\,` does not split)
+fn split_ics_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            ',' => items.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    items.push(current);
+
+    items
+        .iter()
+        .map(|item| unescape_ics_text(item.trim()))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Decode RFC 5545 text escapes in one pass, so `\\n` stays a backslash and an `n`
 fn unescape_ics_text(text: &str) -> String {
-    text.replace("\\n", "\n")
-        .replace("\\,", ",")
-        .replace("\\;", ";")
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(next @ (',' | ';' | '\\')) => unescaped.push(next),
+            // Unknown escape: keep it as it is
+            Some(next) => {
+                unescaped.push('\\');
+                unescaped.push(next);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
 }
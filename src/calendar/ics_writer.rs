@@ -0,0 +1,91 @@
+use super::event::Event;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+
+/// Content lines longer than this (in bytes) are folded (RFC 5545, section 3.1)
+const MAX_LINE_BYTES: usize = 75;
+
+/// Write events as an iCalendar feed named `name`; times are stored in `tz` and exported as UTC
+pub fn write_ics(events: &[Event], name: &str, tz: Tz) -> String {
+    let mut ics = String::new();
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//regular-http-server//Agenda//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape_ics_text(name)));
+
+    for event in events {
+        let (start, end) = match dates(event, tz) {
+            Some(dates) => dates,
+            None => continue,
+        };
+
+        push_line(&mut ics, "BEGIN:VEVENT");
+        // Occurrences of recurring events share the feed's UID; the event id is unique
+        push_line(&mut ics, &format!("UID:{}@regular-http-server", event.id()));
+        push_line(&mut ics, &format!("DTSTAMP:{}", stamp));
+        push_line(&mut ics, &start);
+        push_line(&mut ics, &end);
+        push_line(&mut ics, &format!("SUMMARY:{}", escape_ics_text(&event.title)));
+        if !event.location.is_empty() {
+            push_line(&mut ics, &format!("LOCATION:{}", escape_ics_text(&event.location)));
+        }
+        if !event.description.is_empty() {
+            push_line(&mut ics, &format!("DESCRIPTION:{}", escape_ics_text(&event.description)));
+        }
+        if !event.categories.is_empty() {
+            let categories: Vec<String> = event.categories.iter().map(|c| escape_ics_text(c)).collect();
+            push_line(&mut ics, &format!("CATEGORIES:{}", categories.join(",")));
+        }
+        if !event.conference_url.is_empty() {
+            push_line(&mut ics, &format!("URL:{}", event.conference_url));
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// DTSTART and DTEND lines: dates for all-day events, UTC times otherwise
+fn dates(event: &Event, tz: Tz) -> Option<(String, String)> {
+    if event.is_all_day() {
+        let start = NaiveDate::parse_from_str(&event.start_date, "%Y-%m-%d").ok()?;
+        // DTEND is exclusive; a missing end means one day
+        let end = NaiveDate::parse_from_str(&event.end_date, "%Y-%m-%d").unwrap_or(start + chrono::Duration::days(1));
+        return Some((
+            format!("DTSTART;VALUE=DATE:{}", start.format("%Y%m%d")),
+            format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+        ));
+    }
+
+    let (start, end) = event.time_range(tz)?;
+    let utc = |time: chrono::DateTime<Tz>| time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string();
+    Some((format!("DTSTART:{}", utc(start)), format!("DTEND:{}", utc(end))))
+}
+
+/// Append a content line, folded at MAX_LINE_BYTES without splitting characters
+fn push_line(ics: &mut String, line: &str) {
+    let mut line_bytes = 0;
+    for c in line.chars() {
+        if line_bytes + c.len_utf8() > MAX_LINE_BYTES {
+            // Continuation lines start with a space, which counts towards their length
+            ics.push_str("\r\n ");
+            line_bytes = 1;
+        }
+        ics.push(c);
+        line_bytes += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Escape TEXT values (the reverse of the parser's unescaping)
+fn escape_ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
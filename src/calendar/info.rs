@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use super::storage;
+
+/// Name and color of one calendar (one ICS feed)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalendarInfo {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// CSS color (`#rrggbb` or a color name), empty if unknown
+    #[serde(default)]
+    pub color: String,
+}
+
+const STORAGE_PATH: &str = "data/calendars.json";
+
+/// Colors for calendars without their own color
+const DEFAULT_COLORS: [&str; 6] = ["#ff6b35", "#4a9eff", "#9b59b6", "#2ecc71", "#f1c40f", "#e74c3c"];
+
+/// Calendar id for the feed at `index` in ICS_URLS
+pub fn calendar_id(index: usize) -> String {
+    format!("cal{}", index)
+}
+
+/// Load calendar metadata saved by the last sync
+/// Returns empty vec if neither the file nor its backup can be read
+pub fn load_calendars() -> Vec<CalendarInfo> {
+    storage::load(STORAGE_PATH)
+}
+
+/// Save calendar metadata to JSON file
+pub fn save_calendars(calendars: &[CalendarInfo]) -> Result<(), Box<dyn std::error::Error>> {
+    storage::save(STORAGE_PATH, calendars)
+}
+
+/// Final name and color of every configured calendar:
+/// config override, then feed metadata, then defaults
+pub fn resolve_calendars(config: &Config, feeds: &[CalendarInfo]) -> Vec<CalendarInfo> {
+    let feeds: HashMap<&str, &CalendarInfo> = feeds.iter().map(|info| (info.id.as_str(), info)).collect();
+
+    (0..config.ics_urls.len())
+        .map(|index| {
+            let id = calendar_id(index);
+            let feed = feeds.get(id.as_str());
+            let pick = |configured: Option<&String>, from_feed: Option<&String>| {
+                configured
+                    .filter(|value| !value.is_empty())
+                    .or(from_feed.filter(|value| !value.is_empty()))
+                    .cloned()
+            };
+
+            let name = pick(config.calendar_names.get(index), feed.map(|f| &f.name))
+                .unwrap_or_else(|| format!("Calendar {}", index + 1));
+            let color = pick(config.calendar_colors.get(index), feed.map(|f| &f.color))
+                .unwrap_or_else(|| default_color(index));
+
+            CalendarInfo { id, name, color }
+        })
+        .collect()
+}
+
+/// Default color: the fixed palette first, then evenly spread hues
+fn default_color(index: usize) -> String {
+    match DEFAULT_COLORS.get(index) {
+        Some(color) => color.to_string(),
+        None => format!("hsl({}, 65%, 55%)", (index * 137) % 360),
+    }
+}
+
+/// Normalize a feed or config color to something safe to put into CSS.
+/// Accepts `#rgb`, `#rrggbb`, `#rrggbbaa` (Apple, alpha is dropped) and CSS color names.
+pub fn normalize_color(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return match hex.len() {
+            3 | 6 => Some(format!("#{}", hex)),
+            8 => Some(format!("#{}", &hex[..6])),
+            _ => None,
+        };
+    }
+
+    if !value.is_empty() && value.len() <= 32 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Some(value);
+    }
+
+    None
+}
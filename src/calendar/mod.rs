@@ -1,8 +1,18 @@
+pub mod conference;
 pub mod event;
 pub mod ics_parser;
+pub mod ics_writer;
+pub mod info;
 pub mod fetcher;
 pub mod scheduler;
+pub mod share;
+pub mod storage;
+pub mod store;
+pub mod updates;
 
 // Re-export commonly used items
 pub use event::{load_events, get_last_update};
+pub use info::{load_calendars, resolve_calendars};
 pub use scheduler::start_background_sync;
+pub use store::{EventStore, Snapshot};
+pub use updates::UpdateNotifier;
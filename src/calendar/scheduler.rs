@@ -1,52 +1,148 @@
-use std::thread;
-use std::time::Duration;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use crate::config::Config;
-use super::{event, fetcher, ics_parser};
+use crate::health::Health;
+use crate::metrics::Metrics;
+use crate::shared::Shared;
+use crate::signals::Shutdown;
+use log::{debug, error, info, warn};
+use chrono::Utc;
+use super::store::{EventStore, Snapshot};
+use super::updates::UpdateNotifier;
+use super::{event, fetcher, ics_parser, info};
+
+/// Sync all feeds every `refresh_period` or right away when `refresh` is notified;
+/// the result is saved and put into `events`, and `updates` is notified when it changed.
+/// The thread ends once `shutdown` is requested (and `refresh` notified to wake it).
+pub fn start_background_sync(
+    config: Arc<Shared<Config>>,
+    events: Arc<EventStore>,
+    updates: Arc<UpdateNotifier>,
+    refresh: Arc<UpdateNotifier>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    shutdown: Arc<Shutdown>,
+) -> JoinHandle<()> {
+    register_feeds(&config.get(), &metrics, &health);
 
-pub fn start_background_sync(config: Config) {
     thread::spawn(move || {
         // Small initial delay to let server start
-        println!("[Background] Starting in 5 seconds...");
-        thread::sleep(Duration::from_secs(5));
+        debug!("Starting in 5 seconds");
+        refresh.wait_for_change(refresh.version(), Duration::from_secs(5));
 
         let client = match fetcher::HttpClient::new() {
             Ok(c) => c,
             Err(e) => {
-                eprintln!("[Background] Failed to create HTTP client: {}", e);
+                error!("Failed to create HTTP client, feeds won't be synced: {}", e);
                 return;
             }
         };
 
-        loop {
-            println!("\n[Background] Fetching ICS feeds...");
+        while !shutdown.is_requested() {
+            // Refresh requests arriving during the sync start the next one immediately
+            let refresh_version = refresh.version();
+            // A reloaded configuration applies from the next sync on
+            let config = config.get();
+            register_feeds(&config, &metrics, &health);
+            info!("Fetching ICS feeds");
 
             let mut all_events = Vec::new();
+            // Keep the last known events and metadata of feeds that fail this time
+            let previous = events.snapshot();
+            let mut calendars = previous.calendars().to_vec();
+            let mut fetched = 0;
 
             for (index, url) in config.ics_urls.iter().enumerate() {
+                // Partial results would replace the events of the feeds not fetched yet
+                if shutdown.is_requested() {
+                    info!("Shutting down, sync aborted");
+                    return;
+                }
+                let calendar_id = info::calendar_id(index);
+                let started = Instant::now();
                 match client.fetch_url(url) {
                     Ok(ics_content) => {
-                        let calendar_id = format!("cal{}", index);
                         let events = ics_parser::parse_ics(&ics_content, &config.timezone, &calendar_id);
-                        println!("[Background] ✓ Parsed {} events from {} ({})", events.len(), url, calendar_id);
+                        info!("Parsed {} events from {}", events.len(), calendar_id);
+                        metrics.record_fetch_success(&calendar_id, started.elapsed(), ics_content.len(), events.len());
+                        health.record_success(&calendar_id);
                         all_events.extend(events);
+                        fetched += 1;
+
+                        let calendar_info = ics_parser::parse_calendar_info(&ics_content, &calendar_id);
+                        calendars.retain(|c| c.id != calendar_id);
+                        calendars.push(calendar_info);
                     }
                     Err(e) => {
-                        eprintln!("[Background] ✗ Failed to fetch {}: {}", url, e);
+                        warn!("Failed to fetch {}: {}", calendar_id, e);
+                        metrics.record_fetch_failure(&calendar_id, started.elapsed());
+                        health.record_failure(&calendar_id);
+                        all_events.extend(previous.events().iter().filter(|event| event.calendar_id == calendar_id).cloned());
                     }
                 }
             }
 
-            // Sort by start date
-            all_events.sort_by(|a, b| a.start_date.cmp(&b.start_date));
+            // Without a single fetched feed nothing is new: the stored events (and their
+            // backup) stay as they are, and the cache doesn't count as loaded
+            if fetched == 0 && !config.ics_urls.is_empty() {
+                warn!("No feed could be fetched, keeping the previous events");
+                if previous.tz() != config.tz() {
+                    events.replace(Snapshot::new(previous.events().to_vec(), calendars, config.tz()));
+                    updates.notify();
+                }
+            } else {
+                // Sort by start date
+                all_events.sort_by(|a, b| a.start_date.cmp(&b.start_date));
+
+                // A shutdown waits until the files are written
+                let writing = shutdown.write_guard();
+
+                // Save calendar names and colors
+                calendars.sort_by(|a, b| a.id.cmp(&b.id));
+                if let Err(e) = info::save_calendars(&calendars) {
+                    error!("Failed to save calendars: {}", e);
+                }
+
+                // Only changes are pushed to connected clients
+                let changed = all_events != previous.events() || calendars != previous.calendars();
 
-            // Save to JSON
-            match event::save_events(&all_events) {
-                Ok(_) => println!("[Background] ✓ Saved {} events to data/events.json", all_events.len()),
-                Err(e) => eprintln!("[Background] ✗ Failed to save events: {}", e),
+                // Save to JSON
+                match event::save_events(&all_events) {
+                    Ok(_) => {
+                        info!("Saved {} events to data/events.json", all_events.len());
+                        events.set_last_update(Utc::now().to_rfc3339());
+                    }
+                    Err(e) => error!("Failed to save events: {}", e),
+                }
+                drop(writing);
+
+                // Requests see the new events from here on (even if saving failed);
+                // a new TIMEZONE needs new indexes even if nothing else changed
+                if changed || previous.tz() != config.tz() {
+                    events.replace(Snapshot::new(all_events, calendars, config.tz()));
+                    info!("Events changed, notifying clients");
+                    updates.notify();
+                }
+                health.mark_loaded();
             }
+            metrics.record_sync_run();
 
-            println!("[Background] Sleeping for {} seconds...", config.refresh_period);
-            thread::sleep(Duration::from_secs(config.refresh_period));
+            debug!("Sleeping for {} seconds", config.refresh_period);
+            let period = Duration::from_secs(config.refresh_period);
+            if refresh.wait_for_change(refresh_version, period) != refresh_version {
+                info!("Refresh requested");
+            }
         }
-    });
+        info!("Sync stopped");
+    })
+}
+
+/// Make the configured feeds show up in /metrics and /readyz before their first fetch
+fn register_feeds(config: &Config, metrics: &Metrics, health: &Health) {
+    let feeds: Vec<String> = (0..config.ics_urls.len()).map(info::calendar_id).collect();
+    for feed in &feeds {
+        metrics.add_feed(feed);
+    }
+    health.set_feeds(&feeds);
 }
@@ -0,0 +1,212 @@
+use std::sync::Mutex;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::config::Config;
+use super::storage;
+use super::event::Event;
+use super::info::{resolve_calendars, CalendarInfo};
+
+const STORAGE_PATH: &str = "data/shares.json";
+
+/// Creating and revoking read, change and write the whole file
+static STORAGE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Longest expiry a share can be given (about 100 years)
+const MAX_EXPIRES_IN_DAYS: u32 = 36_500;
+
+/// Title of events in busy-only shares
+const BUSY_TITLE: &str = "Busy";
+
+/// How much of each event a share link shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mask {
+    /// Only the time, titled "Busy"
+    #[default]
+    Busy,
+    /// Everything the agenda shows
+    Full,
+}
+
+impl Mask {
+    pub fn parse(name: &str) -> Option<Mask> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "busy" => Some(Mask::Busy),
+            "full" => Some(Mask::Full),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mask::Busy => "busy",
+            Mask::Full => "full",
+        }
+    }
+}
+
+/// A read-only link (`/s/{token}`) to some of the calendars
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Share {
+    /// Public id, for listing and revoking
+    pub id: String,
+    /// Who or what the link is for
+    pub name: String,
+    /// SHA-256 of the token (hex); the token itself is only shown once, when the share is created
+    pub token_hash: String,
+    /// Calendar ids shown (empty = all calendars)
+    pub calendars: Vec<String>,
+    pub mask: Mask,
+    /// RFC 3339, None = valid until revoked
+    pub expires: Option<String>,
+    pub created: String,
+}
+
+impl Share {
+    pub fn is_expired(&self) -> bool {
+        match &self.expires {
+            Some(expires) => DateTime::parse_from_rfc3339(expires).map(|expires| expires <= Utc::now()).unwrap_or(true),
+            None => false,
+        }
+    }
+
+    pub fn includes_calendar(&self, calendar_id: &str) -> bool {
+        self.calendars.is_empty() || self.calendars.iter().any(|id| id == calendar_id)
+    }
+
+    /// The events this share shows, with details removed for busy-only shares
+    /// (only these are copied, so pass the events of the requested time range)
+    pub fn apply<'a>(&self, events: impl IntoIterator<Item = &'a Event>, my_emails: &[String]) -> Vec<Event> {
+        events
+            .into_iter()
+            .filter(|event| self.includes_calendar(&event.calendar_id))
+            .filter_map(|event| match self.mask {
+                Mask::Full => Some(event.clone()),
+                // Declined events don't make me busy
+                Mask::Busy if event.my_status(my_emails) == Some("DECLINED") => None,
+                Mask::Busy => Some(Event {
+                    uid: event.id(),
+                    title: BUSY_TITLE.to_string(),
+                    start_date: event.start_date.clone(),
+                    end_date: event.end_date.clone(),
+                    location: String::new(),
+                    description: String::new(),
+                    calendar_id: event.calendar_id.clone(),
+                    organizer: None,
+                    attendees: Vec::new(),
+                    conference_url: String::new(),
+                    categories: Vec::new(),
+                }),
+            })
+            .collect()
+    }
+}
+
+/// What to share, from the CLI or the admin API
+#[derive(Debug, Deserialize)]
+pub struct NewShare {
+    pub name: String,
+    #[serde(default)]
+    pub calendars: Vec<String>,
+    #[serde(default)]
+    pub mask: Mask,
+    pub expires_in_days: Option<u32>,
+}
+
+impl NewShare {
+    /// Check the name, the expiry and the calendar ids (against the configured feeds and `calendars` synced from them)
+    pub fn validate(&self, config: &Config, calendars: &[CalendarInfo]) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        match self.expires_in_days {
+            Some(0) => return Err("expires_in_days must be at least 1".to_string()),
+            Some(days) if days > MAX_EXPIRES_IN_DAYS => {
+                return Err(format!("expires_in_days must be at most {}", MAX_EXPIRES_IN_DAYS))
+            }
+            _ => {}
+        }
+        let known = resolve_calendars(config, calendars);
+        match self.calendars.iter().find(|id| !known.iter().any(|calendar| &calendar.id == *id)) {
+            Some(unknown) => Err(format!("unknown calendar {}", unknown)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Load all shares, expired ones included.
+/// Never from the backup, which may still hold revoked shares: an unreadable file is an error.
+pub fn load_shares() -> Result<Vec<Share>, Box<dyn std::error::Error>> {
+    storage::load_current(STORAGE_PATH)
+}
+
+fn save_shares(shares: &[Share]) -> Result<(), Box<dyn std::error::Error>> {
+    storage::save(STORAGE_PATH, shares)
+}
+
+/// The share a link token belongs to
+pub fn find_share(token: &str) -> Result<Option<Share>, Box<dyn std::error::Error>> {
+    let token_hash = hash_token(token);
+    Ok(load_shares()?.into_iter().find(|share| share.token_hash == token_hash))
+}
+
+/// Store a new share; returns it with its token (call `NewShare::validate` first)
+pub fn create_share(new: NewShare) -> Result<(Share, String), Box<dyn std::error::Error>> {
+    let token = random_string(32);
+    let now = Utc::now();
+    let expires = match new.expires_in_days {
+        Some(days) => Some(
+            now.checked_add_signed(Duration::days(days as i64))
+                .ok_or_else(|| format!("expires_in_days {} is out of range", days))?
+                .to_rfc3339(),
+        ),
+        None => None,
+    };
+    let share = Share {
+        id: random_string(6),
+        name: new.name.trim().to_string(),
+        token_hash: hash_token(&token),
+        calendars: new.calendars,
+        mask: new.mask,
+        expires,
+        created: now.to_rfc3339(),
+    };
+
+    let _lock = STORAGE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shares = load_shares()?;
+    shares.push(share.clone());
+    save_shares(&shares)?;
+
+    Ok((share, token))
+}
+
+/// Delete a share; false if there is none with this id
+pub fn revoke_share(id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let _lock = STORAGE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut shares = load_shares()?;
+    let count = shares.len();
+    shares.retain(|share| share.id != id);
+    if shares.len() == count {
+        return Ok(false);
+    }
+    save_shares(&shares)?;
+    Ok(true)
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// URL-safe random string from `bytes` random bytes
+fn random_string(bytes: usize) -> String {
+    let mut random = vec![0; bytes];
+    OsRng.fill_bytes(&mut random);
+    URL_SAFE_NO_PAD.encode(random)
+}
@@ -0,0 +1,142 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Version of the data file layout; files without a header (a bare array) are version 0
+const SCHEMA_VERSION: u32 = 1;
+
+/// Directory all data files live in
+const DATA_DIR: &str = "data";
+
+/// Header and items of a data file (`I` is a slice when writing, a Vec when reading)
+#[derive(Serialize, Deserialize)]
+struct Stored<I> {
+    schema_version: u32,
+    /// When the file was written (RFC 3339)
+    saved_at: String,
+    /// Program version that wrote it
+    generator: String,
+    count: usize,
+    items: I,
+}
+
+/// The header alone (the items are skipped while parsing)
+#[derive(Deserialize)]
+struct StoredHeader {
+    schema_version: u32,
+    saved_at: String,
+}
+
+/// Load the items of a data file; missing files are empty.
+/// A corrupt file is logged and moved aside (`.corrupt`), and the previous generation (`.bak`) used instead.
+pub fn load<T: DeserializeOwned>(path: &str) -> Vec<T> {
+    match read_items(Path::new(path)) {
+        Ok(Some(items)) => return items,
+        Ok(None) => {}
+        Err(e) => {
+            error!("{} is unreadable ({}), falling back to {}", path, e, backup_path(path).display());
+            let corrupt = sibling(path, "corrupt");
+            if let Err(e) = fs::rename(path, &corrupt) {
+                warn!("Failed to move {} to {}: {}", path, corrupt.display(), e);
+            }
+        }
+    }
+
+    let backup = backup_path(path);
+    match read_items(&backup) {
+        Ok(items) => items.unwrap_or_default(),
+        Err(e) => {
+            error!("{} is unreadable too ({}), starting empty", backup.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Load the items of a data file as it is: no backup, nothing moved aside; missing files are empty.
+/// For state where an older generation must not come back (a revoked share link would work again).
+pub fn load_current<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    Ok(read_items(Path::new(path))?.unwrap_or_default())
+}
+
+/// When a data file was last written; None if there is none (or it has no header)
+pub fn saved_at(path: &str) -> Option<String> {
+    let json = fs::read_to_string(path).ok()?;
+    let header: StoredHeader = serde_json::from_str(&json).ok()?;
+    (header.schema_version <= SCHEMA_VERSION).then_some(header.saved_at)
+}
+
+/// Write a data file atomically: readers and crashes see the old file or the new one, never a mix.
+/// The old file is kept as `.bak`.
+pub fn save<T: Serialize>(path: &str, items: &[T]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(DATA_DIR)?;
+    let stored = Stored {
+        schema_version: SCHEMA_VERSION,
+        saved_at: Utc::now().to_rfc3339(),
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        count: items.len(),
+        items,
+    };
+    let json = serde_json::to_vec_pretty(&stored)?;
+
+    let temp = sibling(path, "tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(&json)?;
+    file.sync_all()?;
+    drop(file);
+
+    if Path::new(path).exists() {
+        keep_backup(path)?;
+    }
+    fs::rename(&temp, path)?;
+    sync_dir(path)?;
+    Ok(())
+}
+
+/// Items of a data file, None if it doesn't exist
+fn read_items<T: DeserializeOwned>(path: &Path) -> Result<Option<Vec<T>>, Box<dyn std::error::Error>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Version 0: a bare array, written before the header existed
+    if json.trim_start().starts_with('[') {
+        return Ok(Some(serde_json::from_str(&json)?));
+    }
+
+    let header: StoredHeader = serde_json::from_str(&json)?;
+    if header.schema_version > SCHEMA_VERSION {
+        return Err(format!("schema version {} is newer than this program ({})", header.schema_version, SCHEMA_VERSION).into());
+    }
+    let stored: Stored<Vec<T>> = serde_json::from_str(&json)?;
+    Ok(Some(stored.items))
+}
+
+/// Point `.bak` at the current file; a hard link needs no copy and leaves no moment without the file
+fn keep_backup(path: &str) -> io::Result<()> {
+    let backup = backup_path(path);
+    let _ = fs::remove_file(&backup);
+    fs::hard_link(path, &backup).or_else(|_| fs::copy(path, &backup).map(|_| ()))
+}
+
+fn backup_path(path: &str) -> PathBuf {
+    sibling(path, "bak")
+}
+
+/// "data/events.json" -> "data/events.json.bak"
+fn sibling(path: &str, extension: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path, extension))
+}
+
+/// Make the rename itself durable
+fn sync_dir(path: &str) -> io::Result<()> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
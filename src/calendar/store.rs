@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sha2::{Digest, Sha256};
+use crate::shared::Shared;
+use super::event::Event;
+use super::info::CalendarInfo;
+
+/// The synced events and calendars in memory, shared by the sync (which replaces them)
+/// and the request handlers (which read a snapshot).
+pub struct EventStore {
+    snapshot: Shared<Snapshot>,
+    /// Time of the last sync, also when it changed nothing
+    last_update: Mutex<String>,
+}
+
+/// Events and calendars of one sync, with indexes; never changed once built
+pub struct Snapshot {
+    /// Sorted by start
+    events: Vec<Event>,
+    calendars: Vec<CalendarInfo>,
+    /// Timezone the times were parsed in
+    tz: Tz,
+    /// Hash of the events and calendars (for ETags)
+    fingerprint: String,
+    /// Event id -> position in `events`
+    by_id: HashMap<String, usize>,
+    /// (end, start, position) of every event with parsable times, sorted by end
+    by_end: Vec<(DateTime<Utc>, DateTime<Utc>, usize)>,
+}
+
+impl EventStore {
+    pub fn new(snapshot: Snapshot, last_update: String) -> EventStore {
+        EventStore {
+            snapshot: Shared::new(snapshot),
+            last_update: Mutex::new(last_update),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.get()
+    }
+
+    /// Swap in the result of a sync; readers still holding the old snapshot keep it
+    pub fn replace(&self, snapshot: Snapshot) {
+        self.snapshot.replace(snapshot);
+    }
+
+    pub fn last_update(&self) -> String {
+        self.last_update.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set_last_update(&self, last_update: String) {
+        *self.last_update.lock().unwrap_or_else(|e| e.into_inner()) = last_update;
+    }
+}
+
+impl Snapshot {
+    /// Index `events` (sorted by start); `tz` is the timezone their times are stored in (TIMEZONE)
+    pub fn new(events: Vec<Event>, calendars: Vec<CalendarInfo>, tz: Tz) -> Snapshot {
+        let fingerprint = Sha256::new()
+            .chain_update(serde_json::to_vec(&events).unwrap_or_default())
+            .chain_update(serde_json::to_vec(&calendars).unwrap_or_default())
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let mut by_id = HashMap::new();
+        for (index, event) in events.iter().enumerate() {
+            // Like a search through the list, the first event with an id wins
+            by_id.entry(event.id()).or_insert(index);
+        }
+
+        // Times are parsed once here instead of in every request
+        let mut by_end: Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter_map(|(index, event)| {
+                let (start, end) = event.time_range(tz)?;
+                Some((end.with_timezone(&Utc), start.with_timezone(&Utc), index))
+            })
+            .collect();
+        by_end.sort_by_key(|(end, _, _)| *end);
+
+        Snapshot {
+            events,
+            calendars,
+            tz,
+            fingerprint,
+            by_id,
+            by_end,
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Calendar metadata from the feeds (see `resolve_calendars` for the configured names and colors)
+    pub fn calendars(&self) -> &[CalendarInfo] {
+        &self.calendars
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Event> {
+        self.by_id.get(id).map(|index| &self.events[*index])
+    }
+
+    /// Events overlapping [from, to), sorted by start; only events ending after `from` are looked at
+    pub fn overlapping(&self, from: DateTime<Utc>, to: Option<DateTime<Utc>>) -> Vec<&Event> {
+        // The range is half-open: an event ending exactly at `from` is over
+        let first = self.by_end.partition_point(|(end, _, _)| *end <= from);
+        let mut indexes: Vec<usize> = self.by_end[first..]
+            .iter()
+            .filter(|(_, start, _)| to.map(|to| *start < to).unwrap_or(true))
+            .map(|(_, _, index)| *index)
+            .collect();
+        indexes.sort_unstable();
+        indexes.into_iter().map(|index| &self.events[index]).collect()
+    }
+
+    /// Events in progress or still to come, sorted by start
+    pub fn upcoming(&self, now: DateTime<Utc>) -> Vec<&Event> {
+        self.overlapping(now, None)
+    }
+}
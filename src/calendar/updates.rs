@@ -0,0 +1,59 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Tells waiting clients that a sync changed the stored events.
+///
+/// Every change bumps a version; waiters block until the version differs from the one they know.
+pub struct UpdateNotifier {
+    /// Start time of this process, so versions from a previous run are never mistaken for current ones
+    boot: u64,
+    version: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl UpdateNotifier {
+    pub fn new() -> UpdateNotifier {
+        let boot = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        UpdateNotifier {
+            boot,
+            version: Mutex::new(0),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Record a change and wake all waiters
+    pub fn notify(&self) {
+        let mut version = self.version.lock().unwrap_or_else(|e| e.into_inner());
+        *version += 1;
+        self.changed.notify_all();
+    }
+
+    pub fn version(&self) -> u64 {
+        *self.version.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Block until the version differs from `known` or `timeout` passes; returns the current version
+    pub fn wait_for_change(&self, known: u64, timeout: Duration) -> u64 {
+        let version = self.version.lock().unwrap_or_else(|e| e.into_inner());
+        let (version, _) = self
+            .changed
+            .wait_timeout_while(version, timeout, |version| *version == known)
+            .unwrap_or_else(|e| e.into_inner());
+        *version
+    }
+
+    /// Id of a version as sent to clients ("<boot>-<version>")
+    pub fn event_id(&self, version: u64) -> String {
+        format!("{}-{}", self.boot, version)
+    }
+}
+
+impl Default for UpdateNotifier {
+    fn default() -> UpdateNotifier {
+        UpdateNotifier::new()
+    }
+}
@@ -0,0 +1,77 @@
+use crate::calendar::load_calendars;
+use crate::calendar::share::{self, Mask, NewShare};
+use crate::config::Config;
+use crate::http::auth::hash_password;
+
+const USAGE: &str = "Usage:
+  regular-http-server                      Start the server
+  regular-http-server hash-password        Hash a password read from stdin (for AUTH_USERS_FILE)
+  regular-http-server share create NAME [--calendars cal0,cal1] [--mask busy|full] [--expires-in-days N]
+  regular-http-server share list
+  regular-http-server share revoke ID";
+
+/// Run a command given on the command line (without the program name)
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        ["hash-password"] => {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password).map_err(|e| e.to_string())?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            println!("{}", hash_password(password)?);
+            Ok(())
+        }
+        ["share", "create", name, options @ ..] => create_share(name, options),
+        ["share", "list"] => {
+            for share in share::load_shares().map_err(|e| format!("Failed to load shares: {}", e))? {
+                let calendars = if share.calendars.is_empty() { "all".to_string() } else { share.calendars.join(",") };
+                let expires = match (&share.expires, share.is_expired()) {
+                    (Some(expires), true) => format!("expired {}", expires),
+                    (Some(expires), false) => format!("expires {}", expires),
+                    (None, _) => "never expires".to_string(),
+                };
+                println!("{}  {}  calendars: {}  mask: {}  {}", share.id, share.name, calendars, share.mask.name(), expires);
+            }
+            Ok(())
+        }
+        ["share", "revoke", id] => match share::revoke_share(id).map_err(|e| e.to_string())? {
+            true => {
+                println!("Revoked share {}", id);
+                Ok(())
+            }
+            false => Err(format!("No share with id {}", id)),
+        },
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn create_share(name: &str, options: &[&str]) -> Result<(), String> {
+    let mut new = NewShare {
+        name: name.to_string(),
+        calendars: Vec::new(),
+        mask: Mask::default(),
+        expires_in_days: None,
+    };
+
+    for pair in options.chunks(2) {
+        match pair {
+            ["--calendars", ids] => new.calendars = ids.split(',').map(|id| id.trim().to_string()).collect(),
+            ["--mask", mask] => new.mask = Mask::parse(mask).ok_or_else(|| format!("Invalid mask: {}", mask))?,
+            ["--expires-in-days", days] => {
+                new.expires_in_days = Some(days.parse().map_err(|_| format!("Invalid number of days: {}", days))?)
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    // Calendar ids are checked against the configured feeds (and the names synced from them)
+    let config = Config::load(crate::CONFIG_FILE).map_err(|e| format!("Failed to load .env: {}", e))?;
+    new.validate(&config, &load_calendars())?;
+
+    let (share, token) = share::create_share(new).map_err(|e| e.to_string())?;
+    println!("Created share {} ({})", share.id, share.name);
+    println!("Link: /s/{}", token);
+    println!("The token is not stored and can't be shown again.");
+    Ok(())
+}
@@ -2,6 +2,11 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use crate::calendar::info::normalize_color;
+use crate::logging::{LogFilter, LogFormat};
+
+/// Tag colors available in the agenda stylesheet
+const TAG_COLORS: [&str; 3] = ["green", "blue", "orange"];
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -10,30 +15,97 @@ pub struct Config {
     pub refresh_period: u64,
     pub max_events: usize,
     pub timezone: String,
+    pub my_emails: Vec<String>,
+    pub hide_declined: bool,
+    /// Tag color per category (lowercase category -> green/blue/orange)
+    pub category_colors: HashMap<String, String>,
+    /// Name override per ICS URL (same order, empty = use the feed's name)
+    pub calendar_names: Vec<String>,
+    /// Color override per ICS URL (same order, empty = use the feed's color)
+    pub calendar_colors: Vec<String>,
+    /// Number of HTTP worker threads
+    pub worker_threads: usize,
+    /// Connections waiting for a worker before new ones get 503
+    pub worker_queue_limit: usize,
+    /// Seconds an idle keep-alive connection is kept open
+    pub keep_alive_timeout: u64,
+    /// Requests served on one connection before it is closed
+    pub max_requests_per_connection: usize,
+    /// PEM certificate chain and private key; HTTPS is served when both are set
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    /// Optional plain HTTP listener that redirects to HTTPS
+    pub http_redirect_address: Option<String>,
+    /// Users (`name:argon2-hash[:scopes]`) and API tokens (`name:token[:scopes]`);
+    /// authentication is required when either is set
+    pub auth_users_file: Option<String>,
+    pub auth_tokens_file: Option<String>,
+    /// Key for signing session cookies (generated in data/ when not set)
+    pub session_secret: Option<String>,
+    /// How long a browser login lasts
+    pub session_ttl_hours: u64,
+    /// Networks (CIDR) that may read without logging in, e.g. kiosk screens
+    pub kiosk_networks: Vec<String>,
+    /// Application log levels, e.g. `info` or `warn,http::server=debug`
+    pub log_level: LogFilter,
+    pub log_format: LogFormat,
+    /// `stdout`, `off` or a file to append to
+    pub access_log: String,
+    /// /readyz reports degraded once every feed has failed for this long
+    pub feed_failure_threshold: u64,
+    /// How long a shutdown waits for in-flight requests and the sync
+    pub shutdown_timeout: u64,
 }
 
 impl Display for Config {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "\n * Server address: {} \n * ICS Urls: {} \n * Refresh period: {} \n * Max events {} \n * Timezone: {}",
+            "\n * Server address: {} \n * ICS Urls: {} \n * Refresh period: {} \n * Max events {} \n * Timezone: {} \n * My emails: {} \n * Hide declined: {} \n * Category colors: {:?} \n * Calendar names: {} \n * Calendar colors: {} \n * Worker threads: {} \n * Worker queue limit: {} \n * Keep-alive timeout: {} \n * Max requests per connection: {} \n * TLS certificate: {} \n * HTTP redirect address: {} \n * Users file: {} \n * Tokens file: {} \n * Session secret: {} \n * Session TTL hours: {} \n * Kiosk networks: {} \n * Log level: {} \n * Log format: {} \n * Access log: {} \n * Feed failure threshold: {} \n * Shutdown timeout: {}",
             self.server_address,
             self.ics_urls.join(","),
             self.refresh_period,
             self.max_events,
-            self.timezone
+            self.timezone,
+            self.my_emails.join(","),
+            self.hide_declined,
+            self.category_colors,
+            self.calendar_names.join(","),
+            self.calendar_colors.join(","),
+            self.worker_threads,
+            self.worker_queue_limit,
+            self.keep_alive_timeout,
+            self.max_requests_per_connection,
+            self.tls_cert_path.as_deref().unwrap_or("(none, plain HTTP)"),
+            self.http_redirect_address.as_deref().unwrap_or("(none)"),
+            self.auth_users_file.as_deref().unwrap_or("(none)"),
+            self.auth_tokens_file.as_deref().unwrap_or("(none)"),
+            // Never print the secret itself
+            if self.session_secret.is_some() { "(set)" } else { "(generated)" },
+            self.session_ttl_hours,
+            self.kiosk_networks.join(","),
+            self.log_level,
+            self.log_format.name(),
+            self.access_log,
+            self.feed_failure_threshold,
+            self.shutdown_timeout
         )
     }
 }
 
 impl Config {
+    /// Display timezone (falls back to UTC if TIMEZONE is not a valid IANA name)
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+
     pub fn load(path: &str) -> Result<Self, String> {
         let file = File::open(path).map_err(|e| e.to_string())?;
         let reader = BufReader::new(file);
 
         let vars: HashMap<String, String> = reader
             .lines() // 1. Creates a stream (Iterator) of Result<String>
-            .filter_map(|line_result| line_result.ok()) // 2. Unwrap result, skipping read errors
+            .map_while(Result::ok) // 2. Unwrap result, stopping at read errors
             .map(|line| line.trim().to_string()) // 3. Transform: Trim whitespace
             .filter(|line| !line.is_empty()) // 4. Filter: Skip empty lines
             .filter_map(|line| {
@@ -75,12 +147,186 @@ impl Config {
             .ok_or("Missing TIMEZONE")?
             .clone();
 
+        // Optional: my own addresses, used to find my RSVP status
+        let my_emails: Vec<String> = vars
+            .get("MY_EMAILS")
+            .map(|s| {
+                s.split(',')
+                    .map(|email| email.trim().to_string())
+                    .filter(|email| !email.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Optional: hide declined events instead of dimming them
+        let hide_declined = match vars.get("HIDE_DECLINED_EVENTS") {
+            Some(value) => value.parse().map_err(|_| "Invalid HIDE_DECLINED_EVENTS")?,
+            None => false,
+        };
+
+        // Optional: CATEGORY_COLORS=exam:orange,lab:green
+        let mut category_colors = HashMap::new();
+        if let Some(value) = vars.get("CATEGORY_COLORS") {
+            for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+                let (category, color) = entry
+                    .split_once(':')
+                    .ok_or("Invalid CATEGORY_COLORS (expected category:color)")?;
+                let color = color.trim().to_lowercase();
+                if !TAG_COLORS.contains(&color.as_str()) {
+                    return Err(format!("Invalid CATEGORY_COLORS color: {} (use green, blue or orange)", color));
+                }
+                category_colors.insert(category.trim().to_lowercase(), color);
+            }
+        }
+
+        // Optional: CALENDAR_NAMES=Personal,Work / CALENDAR_COLORS=#ff6b35,,teal
+        let calendar_names: Vec<String> = vars
+            .get("CALENDAR_NAMES")
+            .map(|s| s.split(',').map(|name| name.trim().to_string()).collect())
+            .unwrap_or_default();
+
+        let mut calendar_colors = Vec::new();
+        if let Some(value) = vars.get("CALENDAR_COLORS") {
+            for color in value.split(',').map(|color| color.trim()) {
+                if color.is_empty() {
+                    calendar_colors.push(String::new());
+                } else {
+                    let color = normalize_color(color)
+                        .ok_or_else(|| format!("Invalid CALENDAR_COLORS color: {}", color))?;
+                    calendar_colors.push(color);
+                }
+            }
+        }
+
+        // Optional: HTTP worker pool size and queue limit
+        let worker_threads = match vars.get("WORKER_THREADS") {
+            Some(value) => value.parse().map_err(|_| "Invalid WORKER_THREADS")?,
+            None => 8,
+        };
+        let worker_queue_limit = match vars.get("WORKER_QUEUE_LIMIT") {
+            Some(value) => value.parse().map_err(|_| "Invalid WORKER_QUEUE_LIMIT")?,
+            None => 64,
+        };
+
+        // Optional: persistent connections (a limit of 1 disables keep-alive)
+        let keep_alive_timeout = match vars.get("KEEP_ALIVE_TIMEOUT_SECONDS") {
+            Some(value) => value.parse().map_err(|_| "Invalid KEEP_ALIVE_TIMEOUT_SECONDS")?,
+            None => 5,
+        };
+        let max_requests_per_connection = match vars.get("MAX_REQUESTS_PER_CONNECTION") {
+            Some(value) => match value.parse() {
+                Ok(limit) if limit > 0 => limit,
+                _ => return Err("Invalid MAX_REQUESTS_PER_CONNECTION".to_string()),
+            },
+            None => 100,
+        };
+
+        // Optional: HTTPS (certificate and key go together, the redirect needs HTTPS)
+        let optional = |key: &str| vars.get(key).map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let tls_cert_path = optional("TLS_CERT_PATH");
+        let tls_key_path = optional("TLS_KEY_PATH");
+        if tls_cert_path.is_some() != tls_key_path.is_some() {
+            return Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string());
+        }
+        let http_redirect_address = optional("HTTP_REDIRECT_ADDRESS");
+        if http_redirect_address.is_some() && tls_cert_path.is_none() {
+            return Err("HTTP_REDIRECT_ADDRESS requires TLS_CERT_PATH and TLS_KEY_PATH".to_string());
+        }
+
+        // Optional: authentication (without users or tokens everything is public)
+        let auth_users_file = optional("AUTH_USERS_FILE");
+        let auth_tokens_file = optional("AUTH_TOKENS_FILE");
+        let session_secret = optional("SESSION_SECRET");
+        let session_ttl_hours = match vars.get("SESSION_TTL_HOURS") {
+            Some(value) => match value.parse() {
+                Ok(hours) if hours > 0 => hours,
+                _ => return Err("Invalid SESSION_TTL_HOURS".to_string()),
+            },
+            None => 168,
+        };
+        let kiosk_networks: Vec<String> = vars
+            .get("KIOSK_NETWORKS")
+            .map(|s| {
+                s.split(',')
+                    .map(|network| network.trim().to_string())
+                    .filter(|network| !network.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !kiosk_networks.is_empty() && auth_users_file.is_none() && auth_tokens_file.is_none() {
+            return Err("KIOSK_NETWORKS requires AUTH_USERS_FILE or AUTH_TOKENS_FILE".to_string());
+        }
+
+        // Optional: logging (application log on stderr, access log on stdout by default)
+        let log_level = LogFilter::parse(vars.get("LOG_LEVEL").map(|s| s.as_str()).unwrap_or("info"))?;
+        let log_format = match vars.get("LOG_FORMAT") {
+            Some(value) => LogFormat::parse(value).ok_or("Invalid LOG_FORMAT (use plain or json)")?,
+            None => LogFormat::Plain,
+        };
+        let access_log = optional("ACCESS_LOG").unwrap_or_else(|| "stdout".to_string());
+
+        // Optional: how long all feeds may fail before /readyz reports degraded
+        let feed_failure_threshold = match vars.get("FEED_FAILURE_THRESHOLD_SECONDS") {
+            Some(value) => match value.parse() {
+                Ok(seconds) if seconds > 0 => seconds,
+                _ => return Err("Invalid FEED_FAILURE_THRESHOLD_SECONDS".to_string()),
+            },
+            None => 3600,
+        };
+
+        // Optional: how long SIGTERM waits for requests to finish
+        let shutdown_timeout = match vars.get("SHUTDOWN_TIMEOUT_SECONDS") {
+            Some(value) => value.parse().map_err(|_| "Invalid SHUTDOWN_TIMEOUT_SECONDS")?,
+            None => 10,
+        };
+
         Ok(Config {
             server_address,
             ics_urls,
             refresh_period,
             max_events,
             timezone,
+            my_emails,
+            hide_declined,
+            category_colors,
+            calendar_names,
+            calendar_colors,
+            worker_threads,
+            worker_queue_limit,
+            keep_alive_timeout,
+            max_requests_per_connection,
+            tls_cert_path,
+            tls_key_path,
+            http_redirect_address,
+            auth_users_file,
+            auth_tokens_file,
+            session_secret,
+            session_ttl_hours,
+            kiosk_networks,
+            log_level,
+            log_format,
+            access_log,
+            feed_failure_threshold,
+            shutdown_timeout,
         })
     }
+
+    /// Settings in `new` that differ from this configuration but are only read at startup
+    pub fn restart_required(&self, new: &Config) -> Vec<&'static str> {
+        let changes = [
+            ("SERVER_ADDRESS", self.server_address != new.server_address),
+            ("WORKER_THREADS", self.worker_threads != new.worker_threads),
+            ("WORKER_QUEUE_LIMIT", self.worker_queue_limit != new.worker_queue_limit),
+            ("KEEP_ALIVE_TIMEOUT_SECONDS", self.keep_alive_timeout != new.keep_alive_timeout),
+            ("MAX_REQUESTS_PER_CONNECTION", self.max_requests_per_connection != new.max_requests_per_connection),
+            ("TLS_CERT_PATH", self.tls_cert_path != new.tls_cert_path),
+            ("TLS_KEY_PATH", self.tls_key_path != new.tls_key_path),
+            ("HTTP_REDIRECT_ADDRESS", self.http_redirect_address != new.http_redirect_address),
+            ("LOG_LEVEL", self.log_level.to_string() != new.log_level.to_string()),
+            ("LOG_FORMAT", self.log_format != new.log_format),
+            ("ACCESS_LOG", self.access_log != new.access_log),
+            ("SHUTDOWN_TIMEOUT_SECONDS", self.shutdown_timeout != new.shutdown_timeout),
+        ];
+        changes.iter().filter(|(_, changed)| *changed).map(|(key, _)| *key).collect()
+    }
 }
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+/// State behind `/healthz` and `/readyz`, shared by the HTTP server and the background sync.
+///
+/// Like the metrics, feeds are identified by calendar id, never by URL.
+pub struct Health {
+    started: Instant,
+    /// Events are available: cached on disk, or (on a first start) synced once
+    loaded: AtomicBool,
+    feeds: Mutex<BTreeMap<String, FeedHealth>>,
+}
+
+#[derive(Default)]
+struct FeedHealth {
+    last_success: Option<SystemTime>,
+    /// First failure since the last success
+    failing_since: Option<SystemTime>,
+    consecutive_failures: u64,
+}
+
+/// Outcome of the readiness check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Readiness {
+    /// The cache isn't loaded yet
+    Starting,
+    Ready,
+    /// Every feed has been failing for longer than the threshold; the agenda is stale
+    Degraded,
+}
+
+impl Readiness {
+    pub fn name(self) -> &'static str {
+        match self {
+            Readiness::Starting => "starting",
+            Readiness::Ready => "ready",
+            Readiness::Degraded => "degraded",
+        }
+    }
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health {
+            started: Instant::now(),
+            loaded: AtomicBool::new(false),
+            feeds: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Events can be served from now on
+    pub fn mark_loaded(&self) {
+        self.loaded.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Relaxed)
+    }
+
+    /// The configured feeds; removed ones no longer count for readiness
+    pub fn set_feeds(&self, configured: &[String]) {
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        feeds.retain(|feed, _| configured.contains(feed));
+        for feed in configured {
+            feeds.entry(feed.clone()).or_default();
+        }
+    }
+
+    pub fn record_success(&self, feed: &str) {
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = feeds.entry(feed.to_string()).or_default();
+        stats.last_success = Some(SystemTime::now());
+        stats.failing_since = None;
+        stats.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, feed: &str) {
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = feeds.entry(feed.to_string()).or_default();
+        stats.failing_since.get_or_insert_with(SystemTime::now);
+        stats.consecutive_failures += 1;
+    }
+
+    /// Readiness and a JSON report of each feed; without feeds nothing can be degraded
+    pub fn readiness(&self, threshold: Duration) -> (Readiness, serde_json::Value) {
+        let feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        let now = SystemTime::now();
+        let failing_for = |stats: &FeedHealth| {
+            stats.failing_since.map(|since| now.duration_since(since).unwrap_or_default())
+        };
+
+        let all_failing = !feeds.is_empty()
+            && feeds.values().all(|stats| failing_for(stats).is_some_and(|duration| duration > threshold));
+        let readiness = if !self.is_loaded() {
+            Readiness::Starting
+        } else if all_failing {
+            Readiness::Degraded
+        } else {
+            Readiness::Ready
+        };
+
+        let report: Vec<serde_json::Value> = feeds
+            .iter()
+            .map(|(feed, stats)| {
+                json!({
+                    "feed": feed,
+                    "last_success": stats.last_success.map(|time| DateTime::<Utc>::from(time).to_rfc3339()),
+                    "consecutive_failures": stats.consecutive_failures,
+                    "failing_seconds": failing_for(stats).map(|duration| duration.as_secs()),
+                })
+            })
+            .collect();
+        (readiness, json!(report))
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new()
+    }
+}
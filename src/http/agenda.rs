@@ -0,0 +1,628 @@
+use super::cache;
+use super::description::render_description;
+use super::kiosk::DisplayState;
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::state::AppState;
+use super::url::percent_encode;
+use crate::calendar::resolve_calendars;
+use crate::calendar::event::Event;
+use crate::calendar::info::CalendarInfo;
+use crate::config::Config;
+use chrono::{Duration, Local, NaiveDateTime, TimeZone, Utc};
+
+/// GET / - the agenda page (`?tag=NAME` shows only events with that category), in the view,
+/// calendar and reminder state kiosk commands set. With `?fragment=1` only the contents of
+/// `#agenda` are returned, for in-place updates.
+pub fn index(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let config = &state.config.get();
+    // Optional tag filter: /?tag=exam
+    let tag = request.query.get("tag").map(|tag| tag.trim()).filter(|tag| !tag.is_empty());
+
+    let fragment = request.query.contains_key("fragment");
+
+    let snapshot = state.events.snapshot();
+    let calendars = resolve_calendars(config, snapshot.calendars());
+    let event_id = state.updates.event_id(state.updates.version());
+    let display = state.display.state();
+
+    // The page changes with the events, the view, the display state and (past events, join
+    // buttons) the time, so the ETag covers all of them; a matching request skips rendering entirely
+    let calendars_json = serde_json::to_vec(&calendars).unwrap_or_default();
+    let display_json = serde_json::to_vec(&display).unwrap_or_default();
+    let minute = Local::now().format("%Y-%m-%d %H:%M").to_string();
+    let view: &[u8] = if fragment { b"fragment" } else { b"page" };
+    let etag = cache::content_etag(&[
+        snapshot.fingerprint().as_bytes(),
+        &calendars_json,
+        tag.unwrap_or("").as_bytes(),
+        view,
+        &display_json,
+        event_id.as_bytes(),
+        minute.as_bytes(),
+    ]);
+    if cache::is_fresh(request, &etag, None) {
+        return cache::not_modified(&etag);
+    }
+
+    let events = snapshot.upcoming(Utc::now());
+
+    if fragment {
+        let html = render_agenda(config, &events, &calendars, tag, "/", Some(&display));
+        return Response::html(html).with_header("ETag", &etag);
+    }
+
+    let html = render_page(config, &events, &calendars, tag, "/", Some(&event_id), Some(&display));
+    Response::html(html).with_header("ETag", &etag)
+}
+
+/// The whole agenda page; tag links point to `base` (the agenda or a share link).
+/// With `event_id` the page keeps itself up to date over `/api/stream`.
+pub fn render_page(
+    config: &Config,
+    events: &[&Event],
+    calendars: &[CalendarInfo],
+    tag: Option<&str>,
+    base: &str,
+    event_id: Option<&str>,
+    display: Option<&DisplayState>,
+) -> String {
+    let mut html = String::from(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>MY AGENDA</title>
+    <style>
+        * {
+            margin: 0;
+            padding: 0;
+            box-sizing: border-box;
+        }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
+            background-color: #000;
+            color: #fff;
+            padding: 40px 20px;
+            max-width: 700px;
+            margin: 0 auto;
+        }
+        .header {
+            text-align: right;
+            font-size: 16px;
+            letter-spacing: 2px;
+            color: #999;
+            margin-bottom: 40px;
+        }
+        .legend {
+            display: flex;
+            flex-wrap: wrap;
+            justify-content: flex-end;
+            gap: 16px;
+            color: #999;
+            font-size: 14px;
+            margin-top: -30px;
+            margin-bottom: 40px;
+        }
+        .legend-item {
+            display: flex;
+            align-items: center;
+        }
+        .legend .event-dot {
+            width: 10px;
+            height: 10px;
+            margin-top: 0;
+            margin-right: 6px;
+        }
+        .day-section {
+            margin-bottom: 30px;
+            border-bottom: 1px solid #333;
+            padding-bottom: 20px;
+        }
+        .day-header {
+            font-size: 22px;
+            font-weight: bold;
+            text-transform: uppercase;
+            margin-bottom: 15px;
+            letter-spacing: 1px;
+        }
+        .event-item {
+            display: flex;
+            align-items: flex-start;
+            margin-bottom: 15px;
+            padding-left: 0;
+        }
+        .event-dot {
+            width: 14px;
+            height: 14px;
+            border-radius: 50%;
+            margin-right: 12px;
+            margin-top: 5px;
+            flex-shrink: 0;
+        }
+        .event-dot.active {
+            background-color: #ff6b35;
+        }
+        .event-dot.past {
+            background-color: #666;
+        }
+        .event-dot.video {
+            background-color: #4a9eff;
+            box-shadow: 0 0 0 3px rgba(74, 158, 255, 0.4);
+        }
+        .event-content {
+            flex: 1;
+        }
+        .event-time {
+            color: #ccc;
+            font-size: 26px;
+            margin-right: 10px;
+        }
+        .event-title {
+            color: #fff;
+            font-size: 28px;
+            display: inline;
+            font-weight: 500;
+        }
+        .event-tag {
+            display: inline-block;
+            background-color: #4a4a4a;
+            color: #fff;
+            padding: 2px 10px;
+            border-radius: 4px;
+            font-size: 12px;
+            margin-right: 6px;
+            margin-bottom: 4px;
+            text-decoration: none;
+        }
+        .event-tag.green {
+            background-color: #2d5f2e;
+        }
+        .event-tag.blue {
+            background-color: #1e3a5f;
+        }
+        .event-tag.orange {
+            background-color: #5f3a1e;
+        }
+        .event-tags {
+            margin-top: 6px;
+        }
+        .event-status {
+            color: #999;
+            font-size: 12px;
+            margin-left: 8px;
+        }
+        .event-location {
+            color: #999;
+            font-size: 14px;
+            text-align: right;
+            margin-top: -20px;
+            margin-bottom: 10px;
+        }
+        .event-item.declined {
+            opacity: 0.4;
+        }
+        .event-item.declined .event-title {
+            text-decoration: line-through;
+        }
+        .event-badge {
+            display: inline-block;
+            background-color: #ff6b35;
+            color: #000;
+            padding: 2px 8px;
+            border-radius: 4px;
+            font-size: 12px;
+            font-weight: bold;
+            margin-left: 8px;
+            vertical-align: middle;
+        }
+        .event-attendees {
+            color: #999;
+            font-size: 14px;
+            margin-top: 4px;
+        }
+        .join-button {
+            display: inline-block;
+            color: #4a9eff;
+            border: 1px solid #4a9eff;
+            padding: 2px 10px;
+            border-radius: 4px;
+            font-size: 14px;
+            text-decoration: none;
+            margin-left: 8px;
+            vertical-align: middle;
+        }
+        .join-button.soon {
+            background-color: #4a9eff;
+            color: #000;
+            font-size: 20px;
+            font-weight: bold;
+            padding: 6px 18px;
+        }
+        .event-description {
+            color: #999;
+            font-size: 14px;
+            margin-left: 26px;
+            margin-top: 6px;
+        }
+        .event-description a {
+            color: #4a9eff;
+        }
+        .event-description.collapsible .description-body {
+            max-height: 5.5em;
+            overflow: hidden;
+        }
+        .event-description.collapsible.expanded .description-body {
+            max-height: none;
+        }
+        .description-toggle {
+            background: none;
+            border: none;
+            color: #4a9eff;
+            font-size: 14px;
+            cursor: pointer;
+            padding: 0;
+        }
+        .description-toggle::after {
+            content: "more";
+        }
+        .event-description.expanded .description-toggle::after {
+            content: "less";
+        }
+        .no-events {
+            text-align: center;
+            padding: 60px 20px;
+            color: #666;
+            font-size: 14px;
+        }
+"#);
+
+    // Calendar colors are generated per calendar (feed metadata or config override)
+    for calendar in calendars {
+        html.push_str(&format!(
+            "        .event-dot.{} {{\n            background-color: {};\n        }}\n",
+            calendar.id, calendar.color
+        ));
+    }
+
+    html.push_str("    </style>\n</head>\n<body>\n");
+
+    // Header shows the active tag filter
+    match tag {
+        Some(tag) => html.push_str(&format!(
+            "    <div class=\"header\">MY AGENDA · {}</div>\n",
+            escape_html(&tag.to_uppercase())
+        )),
+        None => html.push_str("    <div class=\"header\">MY AGENDA</div>\n"),
+    }
+
+    let event_id = match event_id {
+        Some(event_id) => event_id,
+        None => {
+            html.push_str(&render_agenda(config, events, calendars, tag, base, display));
+            html.push_str("</body></html>");
+            return html;
+        }
+    };
+
+    // Events are wrapped so the script can replace them without reloading the page
+    html.push_str(&format!(
+        "    <div id=\"agenda\" data-event-id=\"{}\">\n",
+        escape_html(event_id)
+    ));
+    html.push_str(&render_agenda(config, events, calendars, tag, base, display));
+    html.push_str("    </div>\n");
+
+    // Server-Sent Events: replace #agenda when a sync or a kiosk command changed something
+    html.push_str(r#"
+    <script>
+        const agenda = document.getElementById('agenda');
+
+        function refreshAgenda() {
+            const url = new URL(location.href);
+            url.searchParams.set('fragment', '1');
+            fetch(url)
+                .then(r => r.ok ? r.text() : Promise.reject(r.status))
+                .then(html => { agenda.innerHTML = html; })
+                .catch(err => console.error('Failed to refresh agenda:', err));
+        }
+
+        // The id of the rendered state is passed along, so updates missed
+        // while the page loaded are sent right away; reconnects use Last-Event-ID
+        const stream = new EventSource('/api/stream?last_event_id=' + encodeURIComponent(agenda.dataset.eventId));
+        stream.addEventListener('update', refreshAgenda);
+    </script>
+"#);
+
+    html.push_str("</body></html>");
+    html
+}
+
+/// Legend and day sections of the agenda (the contents of `#agenda`).
+/// `display` is the kiosk state: the view, a calendar to show alone and acknowledged reminders.
+fn render_agenda(
+    config: &Config,
+    events: &[&Event],
+    calendars: &[CalendarInfo],
+    tag: Option<&str>,
+    base: &str,
+    display: Option<&DisplayState>,
+) -> String {
+    let now = Local::now();
+    let only_calendar = display.and_then(|display| display.calendar.as_deref());
+    let (limit, horizon) = view_window(display.map(|display| display.view.as_str()), now.naive_local());
+
+    // Filter events: only show current or future events (end_date >= now).
+    // Callers pass upcoming events already; this also leaves out all-day events, which have no time.
+    let future_events: Vec<_> = events
+        .iter()
+        .copied()
+        .filter(|event| {
+            // Parse end_date (format: YYYY-MM-DD HH:MM:SS)
+            if let Ok(end_dt) = NaiveDateTime::parse_from_str(&event.end_date, "%Y-%m-%d %H:%M:%S") {
+                // Convert to Local timezone for comparison
+                let end_local = Local::now().timezone().from_local_datetime(&end_dt).single();
+                if let Some(end_time) = end_local {
+                    return end_time >= now;
+                }
+            }
+            false // If parsing fails, exclude the event
+        })
+        .filter(|event| {
+            // Optionally hide events I declined
+            !(config.hide_declined && event.my_status(&config.my_emails) == Some("DECLINED"))
+        })
+        .filter(|event| tag.map(|tag| event.has_tag(tag)).unwrap_or(true))
+        .filter(|event| only_calendar.map(|id| event.calendar_id == id).unwrap_or(true))
+        .filter(|event| match (horizon, NaiveDateTime::parse_from_str(&event.start_date, "%Y-%m-%d %H:%M:%S")) {
+            (Some(horizon), Ok(start)) => start < horizon,
+            _ => true,
+        })
+        .collect();
+
+    let events_to_show: Vec<_> = future_events.iter().take(limit).collect();
+
+    let calendars: Vec<_> = calendars
+        .iter()
+        .filter(|calendar| only_calendar.map(|id| calendar.id == id).unwrap_or(true))
+        .collect();
+
+    let mut html = String::new();
+
+    // Legend with calendar names (only useful with more than one calendar)
+    if calendars.len() > 1 {
+        html.push_str("    <div class=\"legend\">\n");
+        for calendar in calendars {
+            html.push_str(&format!(
+                "        <span class=\"legend-item\"><span class=\"event-dot {}\"></span>{}</span>\n",
+                calendar.id,
+                escape_html(&calendar.name)
+            ));
+        }
+        html.push_str("    </div>\n");
+    }
+
+    if events_to_show.is_empty() {
+        html.push_str(r#"<div class="no-events">No events found. Check back later!</div>"#);
+    } else {
+        let mut last_date = String::new();
+
+        for event in events_to_show {
+            // Extract date from start_date (assuming format: YYYY-MM-DD HH:MM:SS)
+            let date_part = event.start_date.split_whitespace().next().unwrap_or("");
+
+            // If new day, create day section
+            if date_part != last_date {
+                if !last_date.is_empty() {
+                    html.push_str("    </div>\n");
+                }
+
+                html.push_str(&format!(r#"    <div class="day-section">
+        <div class="day-header">{}</div>
+"#, format_date(date_part)));
+
+                last_date = date_part.to_string();
+            }
+
+            // Extract time from start and end
+            let start_time = extract_time(&event.start_date);
+            let end_time = extract_time(&event.end_date);
+
+            // Use calendar_id for color (fallback to "active" if empty)
+            let mut dot_class = if !event.calendar_id.is_empty() {
+                event.calendar_id.clone()
+            } else {
+                "active".to_string()
+            };
+            if !event.conference_url.is_empty() {
+                dot_class.push_str(" video");
+            }
+
+            // My participation status (only if I'm invited)
+            let my_status = event.my_status(&config.my_emails);
+            let item_class = if my_status == Some("DECLINED") {
+                "event-item declined"
+            } else {
+                "event-item"
+            };
+
+            html.push_str(&format!(r#"        <div class="{}">
+            <div class="event-dot {}"></div>
+            <div class="event-content">
+                <span class="event-time">{} - {}</span>
+                <span class="event-title">{}</span>
+"#,
+                item_class,
+                dot_class,
+                escape_html(&start_time),
+                escape_html(&end_time),
+                escape_html(&event.title)
+            ));
+
+            // Badge or status for my RSVP
+            match my_status {
+                Some("NEEDS-ACTION") => html.push_str(r#"<span class="event-badge">RSVP</span>"#),
+                Some("TENTATIVE") => html.push_str(r#"<span class="event-status">Maybe</span>"#),
+                Some("DECLINED") => html.push_str(r#"<span class="event-status">Declined</span>"#),
+                _ => {}
+            }
+
+            // Join button for video calls (prominent shortly before start)
+            if !event.conference_url.is_empty() {
+                // An acknowledged reminder stops highlighting the button
+                let acknowledged = display.map(|display| display.acknowledged.contains(&event.id())).unwrap_or(false);
+                let button_class = if is_starting_soon(event, config) && !acknowledged {
+                    "join-button soon"
+                } else {
+                    "join-button"
+                };
+                html.push_str(&format!(
+                    r#"<a class="{}" href="{}" target="_blank" rel="noopener noreferrer">Join</a>"#,
+                    button_class,
+                    escape_html(&event.conference_url)
+                ));
+            }
+
+            // Add tags (categories) as chips
+            if !event.categories.is_empty() {
+                html.push_str(r#"
+                <div class="event-tags">"#);
+                for category in &event.categories {
+                    let tag_class = match config.category_colors.get(&category.to_lowercase()) {
+                        Some(color) => format!("event-tag {}", color),
+                        None => "event-tag".to_string(),
+                    };
+                    html.push_str(&format!(
+                        r#"<a class="{}" href="{}?tag={}">{}</a>"#,
+                        tag_class,
+                        escape_html(base),
+                        escape_html(&percent_encode(category)),
+                        escape_html(category)
+                    ));
+                }
+                html.push_str("</div>\n");
+            }
+
+            // Add organizer and attendees if present
+            if let Some(attendees_line) = format_attendees(event, &config.my_emails) {
+                html.push_str(&format!(r#"
+                <div class="event-attendees">{}</div>
+"#, attendees_line));
+            }
+
+            // Add location if present
+            if !event.location.is_empty() {
+                html.push_str(&format!(r#"
+                <div class="event-location">{}</div>
+"#, escape_html(&event.location)));
+            }
+
+            // Add description if present (sanitized, with links)
+            if !event.description.is_empty() {
+                html.push_str(&format!(r#"
+                {}
+"#, render_description(&event.description)));
+            }
+
+            html.push_str("            </div>\n        </div>\n");
+        }
+
+        if !last_date.is_empty() {
+            html.push_str("    </div>\n");
+        }
+    }
+
+    html
+}
+
+/// How many events a kiosk view shows and before when they must start:
+/// "agenda" the next 10, "day" the rest of today, "week" the next 7 days, "next" only one
+fn view_window(view: Option<&str>, now: NaiveDateTime) -> (usize, Option<NaiveDateTime>) {
+    match view {
+        Some("day") => (usize::MAX, (now.date() + Duration::days(1)).and_hms_opt(0, 0, 0)),
+        Some("week") => (usize::MAX, Some(now + Duration::days(7))),
+        Some("next") => (1, None),
+        _ => (10, None),
+    }
+}
+
+/// How long before the start a join link becomes prominent
+const JOIN_SOON_MINUTES: i64 = 10;
+
+/// True from JOIN_SOON_MINUTES before the start until the event ends
+pub fn is_starting_soon(event: &Event, config: &Config) -> bool {
+    let tz = config.tz();
+    let now = Utc::now().with_timezone(&tz);
+    match (event.start_time(tz), event.end_time(tz)) {
+        (Some(start), Some(end)) => now >= start - Duration::minutes(JOIN_SOON_MINUTES) && now <= end,
+        _ => false,
+    }
+}
+
+/// "Organizer: Jane · with John, Anna +3" (HTML-escaped), or None if nobody is listed
+fn format_attendees(event: &Event, my_emails: &[String]) -> Option<String> {
+    const MAX_NAMES: usize = 3;
+
+    let mut parts = Vec::new();
+
+    if let Some(organizer) = &event.organizer {
+        if !organizer.has_email(my_emails) {
+            parts.push(format!("Organizer: {}", escape_html(organizer.display_name())));
+        }
+    }
+
+    let others: Vec<_> = event
+        .other_attendees(my_emails)
+        .filter(|attendee| attendee.status != "DECLINED")
+        .collect();
+    if !others.is_empty() {
+        let names: Vec<String> = others
+            .iter()
+            .take(MAX_NAMES)
+            .map(|attendee| escape_html(attendee.display_name()))
+            .collect();
+        let mut with = format!("with {}", names.join(", "));
+        if others.len() > MAX_NAMES {
+            with.push_str(&format!(" +{}", others.len() - MAX_NAMES));
+        }
+        parts.push(with);
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · "))
+    }
+}
+
+fn format_date(date_str: &str) -> String {
+    // Input format: YYYY-MM-DD
+    // Output format: DD. MONTH
+    let parts: Vec<&str> = date_str.split('-').collect();
+    if parts.len() == 3 {
+        let months = ["", "JANUARY", "FEBRUARY", "MARCH", "APRIL", "MAY", "JUNE",
+                     "JULY", "AUGUST", "SEPTEMBER", "OCTOBER", "NOVEMBER", "DECEMBER"];
+        let month_idx: usize = parts[1].parse().unwrap_or(1);
+        let day = parts[2].parse::<u32>().unwrap_or(1);
+        format!("{}. {}", day, months.get(month_idx).unwrap_or(&"UNKNOWN"))
+    } else {
+        date_str.to_string()
+    }
+}
+
+fn extract_time(datetime: &str) -> String {
+    // Extract HH:MM from "YYYY-MM-DD HH:MM:SS"
+    datetime.split_whitespace()
+        .nth(1)
+        .and_then(|time| time.split(':').take(2).collect::<Vec<_>>().join(":").into())
+        .unwrap_or_else(|| "00:00".to_string())
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
\ No newline at end of file
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use serde::Serialize;
+use super::agenda::is_starting_soon;
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::state::AppState;
+use crate::calendar::{resolve_calendars, Snapshot};
+use crate::calendar::event::{Attendee, Event};
+use crate::calendar::info::CalendarInfo;
+use crate::calendar::share::Share;
+use crate::config::Config;
+use crate::health::Readiness;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Version of the events API schema (see README, "Events API")
+const API_VERSION: u32 = 1;
+
+/// Page size when `limit` is not given, and the largest allowed
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// GET /api/last-update - timestamp of the last sync
+pub fn last_update(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    let timestamp = state.events.last_update();
+    Response::json(200, &serde_json::json!({ "last_update": timestamp }))
+}
+
+/// POST /api/refresh - sync the feeds now instead of waiting for the next period
+pub fn refresh(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    state.refresh.notify();
+    Response::json(202, &serde_json::json!({ "status": "refresh scheduled" }))
+}
+
+/// GET /metrics - Prometheus text exposition format
+pub fn metrics(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    Response::text(200, &state.metrics.render()).with_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+}
+
+/// GET /healthz - liveness: the process is up and its listener answers
+pub fn healthz(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    Response::json(
+        200,
+        &serde_json::json!({
+            "status": "ok",
+            "uptime_seconds": state.health.uptime().as_secs(),
+        }),
+    )
+}
+
+/// GET /readyz - readiness: 200 once events are loaded, 503 while starting or
+/// when every feed has been failing for longer than FEED_FAILURE_THRESHOLD_SECONDS
+pub fn readyz(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    let threshold = Duration::from_secs(state.config.get().feed_failure_threshold);
+    let (readiness, feeds) = state.health.readiness(threshold);
+    let status = if readiness == Readiness::Ready { 200 } else { 503 };
+    Response::json(
+        status,
+        &serde_json::json!({
+            "status": readiness.name(),
+            "cache_loaded": state.health.is_loaded(),
+            "failure_threshold_seconds": threshold.as_secs(),
+            "feeds": feeds,
+        }),
+    )
+}
+
+/// GET /api/next - the current or next event with its join link (desk devices)
+pub fn next_event(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    let config = &state.config.get();
+    Response::json(200, &next_event_json(config, &state.events.snapshot()))
+}
+
+/// The event in progress or coming up next, with its join link
+fn next_event_json(config: &Config, snapshot: &Snapshot) -> serde_json::Value {
+    let tz = config.tz();
+    let now = Utc::now().with_timezone(&tz);
+
+    // All-day events have no end time and are skipped
+    let next = snapshot.upcoming(now.with_timezone(&Utc)).into_iter().find(|event| {
+        event.my_status(&config.my_emails) != Some("DECLINED")
+            && event.end_time(tz).map(|end| end >= now).unwrap_or(false)
+    });
+
+    match next {
+        Some(event) => {
+            let start = event.start_time(tz);
+            serde_json::json!({
+                "event": {
+                    "title": event.title,
+                    "start": start.map(|t| t.to_rfc3339()),
+                    "end": event.end_time(tz).map(|t| t.to_rfc3339()),
+                    "location": event.location,
+                    "calendar_id": event.calendar_id,
+                    "in_progress": start.map(|t| t <= now).unwrap_or(false),
+                    "starts_in_minutes": start.map(|t| (t - now).num_minutes().max(0)),
+                    "join_url": if event.conference_url.is_empty() { None } else { Some(&event.conference_url) },
+                    "join_soon": is_starting_soon(event, config),
+                }
+            })
+        }
+        None => serde_json::json!({ "event": null }),
+    }
+}
+
+/// One event in the v1 schema
+#[derive(Serialize)]
+struct EventV1 {
+    id: String,
+    calendar: CalendarV1,
+    title: String,
+    all_day: bool,
+    /// RFC 3339 in the presentation timezone, or YYYY-MM-DD for all-day events
+    start: String,
+    /// Exclusive end, same format as `start`
+    end: String,
+    location: String,
+    description: String,
+    tags: Vec<String>,
+    conference_url: Option<String>,
+    organizer: Option<PersonV1>,
+    attendees: Vec<PersonV1>,
+}
+
+#[derive(Serialize)]
+struct CalendarV1 {
+    id: String,
+    name: String,
+    color: String,
+}
+
+#[derive(Serialize)]
+struct PersonV1 {
+    email: String,
+    name: String,
+    /// PARTSTAT, e.g. ACCEPTED or NEEDS-ACTION
+    status: String,
+    role: String,
+}
+
+#[derive(Serialize)]
+struct EventListV1 {
+    version: u32,
+    timezone: String,
+    events: Vec<EventV1>,
+    /// Pass as `cursor` to get the next page; null on the last page
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EventResponseV1 {
+    version: u32,
+    timezone: String,
+    event: EventV1,
+}
+
+#[derive(Serialize)]
+struct ErrorV1 {
+    version: u32,
+    error: String,
+}
+
+pub fn api_error(status: u16, message: &str) -> Response {
+    Response::json(
+        status,
+        &ErrorV1 {
+            version: API_VERSION,
+            error: message.to_string(),
+        },
+    )
+}
+
+/// GET /api/events - events as JSON
+///
+/// Query parameters: `from`/`to` (RFC 3339 or YYYY-MM-DD, default from now),
+/// `calendar` (comma-separated ids), `tag`, `q` (text search), `limit`, `cursor`
+/// and `tz` (presentation timezone, default TIMEZONE).
+pub fn list_events(state: &AppState, request: &Request, _params: &Params) -> Response {
+    event_list(&state.config.get(), request, &state.events.snapshot(), None)
+}
+
+/// The v1 list of the events in `snapshot` matching the request's query parameters;
+/// with a `share`, only what the share link shows
+pub fn event_list(config: &Config, request: &Request, snapshot: &Snapshot, share: Option<&Share>) -> Response {
+    let query = &request.query;
+    let source_tz = config.tz();
+    let tz = match presentation_tz(request, source_tz) {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+
+    let from = match query.get("from") {
+        Some(value) => match parse_bound(value, tz) {
+            Some(from) => from,
+            None => return api_error(400, "invalid from"),
+        },
+        None => Utc::now(),
+    };
+    let to = match query.get("to") {
+        Some(value) => match parse_bound(value, tz) {
+            Some(to) => Some(to),
+            None => return api_error(400, "invalid to"),
+        },
+        None => None,
+    };
+
+    let limit = match query.get("limit") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(limit) if limit > 0 => limit.min(MAX_LIMIT),
+            _ => return api_error(400, "invalid limit"),
+        },
+        None => DEFAULT_LIMIT,
+    };
+    // The cursor is the offset of the next page
+    let offset = match query.get("cursor") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(offset) => offset,
+            Err(_) => return api_error(400, "invalid cursor"),
+        },
+        None => 0,
+    };
+
+    let calendars: Option<Vec<&str>> = query
+        .get("calendar")
+        .map(|value| value.split(',').map(|id| id.trim()).collect());
+    let tag = query.get("tag");
+    let text = query.get("q").map(|q| q.to_lowercase());
+
+    let in_range = snapshot.overlapping(from, to);
+    // Masked before filtering, so a busy-only share can't be searched for hidden titles
+    let masked: Vec<Event>;
+    let in_range = match share {
+        Some(share) => {
+            masked = share.apply(in_range, &config.my_emails);
+            masked.iter().collect()
+        }
+        None => in_range,
+    };
+
+    let matching: Vec<&Event> = in_range
+        .into_iter()
+        .filter(|event| {
+            calendars
+                .as_ref()
+                .map(|ids| ids.contains(&event.calendar_id.as_str()))
+                .unwrap_or(true)
+        })
+        .filter(|event| tag.map(|tag| event.has_tag(tag)).unwrap_or(true))
+        .filter(|event| text.as_ref().map(|text| matches_text(event, text)).unwrap_or(true))
+        .collect();
+
+    let calendar_infos = calendar_map(config, snapshot);
+    let page: Vec<EventV1> = matching
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|event| to_v1(event, source_tz, tz, &calendar_infos))
+        .collect();
+    // The cursor comes from the client and may be anything up to usize::MAX
+    let next = offset.saturating_add(limit);
+    let next_cursor = if next < matching.len() {
+        Some(next.to_string())
+    } else {
+        None
+    };
+
+    Response::json(
+        200,
+        &EventListV1 {
+            version: API_VERSION,
+            timezone: tz.name().to_string(),
+            events: page,
+            next_cursor,
+        },
+    )
+}
+
+/// GET /api/events/:id - a single event
+pub fn get_event(state: &AppState, request: &Request, params: &Params) -> Response {
+    let config = &state.config.get();
+    let source_tz = config.tz();
+    let tz = match presentation_tz(request, source_tz) {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+
+    let id = params.get("id").map(|id| id.as_str()).unwrap_or("");
+    let snapshot = state.events.snapshot();
+    match snapshot.find(id) {
+        Some(event) => Response::json(
+            200,
+            &EventResponseV1 {
+                version: API_VERSION,
+                timezone: tz.name().to_string(),
+                event: to_v1(event, source_tz, tz, &calendar_map(config, &snapshot)),
+            },
+        ),
+        None => api_error(404, "event not found"),
+    }
+}
+
+/// Timezone from the `tz` query parameter, defaulting to the configured one
+fn presentation_tz(request: &Request, default: Tz) -> Result<Tz, Response> {
+    match request.query.get("tz") {
+        Some(name) => name.parse().map_err(|_| api_error(400, "invalid tz")),
+        None => Ok(default),
+    }
+}
+
+/// Parse a range bound: RFC 3339, or a date meaning midnight in `tz`
+fn parse_bound(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    tz.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|datetime| datetime.with_timezone(&Utc))
+}
+
+fn matches_text(event: &Event, text: &str) -> bool {
+    [&event.title, &event.location, &event.description]
+        .iter()
+        .any(|field| field.to_lowercase().contains(text))
+}
+
+fn calendar_map(config: &Config, snapshot: &Snapshot) -> HashMap<String, CalendarInfo> {
+    resolve_calendars(config, snapshot.calendars())
+        .into_iter()
+        .map(|info| (info.id.clone(), info))
+        .collect()
+}
+
+/// Convert a stored event to the v1 schema, presenting times in `tz`
+fn to_v1(event: &Event, source_tz: Tz, tz: Tz, calendars: &HashMap<String, CalendarInfo>) -> EventV1 {
+    let all_day = event.is_all_day();
+    let (start, end) = match (all_day, event.time_range(source_tz)) {
+        (false, Some((start, end))) => (
+            start.with_timezone(&tz).to_rfc3339(),
+            end.with_timezone(&tz).to_rfc3339(),
+        ),
+        _ => (event.start_date.clone(), event.end_date.clone()),
+    };
+    let calendar = calendars.get(&event.calendar_id);
+
+    EventV1 {
+        id: event.id(),
+        calendar: CalendarV1 {
+            id: event.calendar_id.clone(),
+            name: calendar.map(|c| c.name.clone()).unwrap_or_default(),
+            color: calendar.map(|c| c.color.clone()).unwrap_or_default(),
+        },
+        title: event.title.clone(),
+        all_day,
+        start,
+        end,
+        location: event.location.clone(),
+        description: event.description.clone(),
+        tags: event.categories.clone(),
+        conference_url: if event.conference_url.is_empty() {
+            None
+        } else {
+            Some(event.conference_url.clone())
+        },
+        organizer: event.organizer.as_ref().map(person_v1),
+        attendees: event.attendees.iter().map(person_v1).collect(),
+    }
+}
+
+fn person_v1(attendee: &Attendee) -> PersonV1 {
+    PersonV1 {
+        email: attendee.email.clone(),
+        name: attendee.name.clone(),
+        status: attendee.status.clone(),
+        role: attendee.role.clone(),
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::state::AppState;
+use super::url::percent_encode;
+use crate::config::Config;
+use log::info;
+
+/// Name of the login session cookie
+pub const SESSION_COOKIE: &str = "session";
+
+/// Generated session key, used when SESSION_SECRET is not set
+const SESSION_KEY_FILE: &str = "data/session.key";
+
+/// Realm shown in browser password prompts
+const REALM: &str = "Agenda";
+
+/// Verified Basic credentials remembered, so argon2 runs once per password rather than per request
+const MAX_VERIFIED: usize = 1024;
+
+/// What a user or token may do; `admin` includes the others
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// View the agenda and the read-only API
+    Read,
+    /// Trigger a sync of the feeds
+    Refresh,
+    /// Send commands to the kiosk screens
+    Control,
+    Admin,
+}
+
+impl Scope {
+    fn parse(name: &str) -> Option<Scope> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "read" => Some(Scope::Read),
+            "refresh" => Some(Scope::Refresh),
+            "control" => Some(Scope::Control),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Who sent a request
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Identity {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+}
+
+struct User {
+    /// argon2 PHC string ("$argon2id$v=19$...")
+    password_hash: String,
+    scopes: Vec<Scope>,
+}
+
+/// Network in CIDR notation ("192.168.10.0/24")
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(value: &str) -> Result<Network, String> {
+        let invalid = || format!("Invalid KIOSK_NETWORKS entry: {}", value);
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().ok().filter(|p| *p <= max_prefix).ok_or_else(invalid)?,
+            None => max_prefix,
+        };
+
+        Ok(Network { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        let mask = |prefix: u32| if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+
+        match (self.addr, ip) {
+            // Aligned to the top bits, so the same mask works for both families
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                ((u32::from(net) as u128) << 96) & mask(self.prefix) == ((u32::from(ip) as u128) << 96) & mask(self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => u128::from(net) & mask(self.prefix) == u128::from(ip) & mask(self.prefix),
+            _ => false,
+        }
+    }
+}
+
+/// Users, API tokens and sessions.
+///
+/// Auth is on when a users or tokens file is configured; otherwise every request is allowed.
+pub struct Auth {
+    enabled: bool,
+    users: HashMap<String, User>,
+    /// SHA-256 of each token, so the tokens themselves are not kept around
+    tokens: HashMap<[u8; 32], Identity>,
+    /// Clients here may read without logging in (kiosk screens)
+    kiosk_networks: Vec<Network>,
+    session_key: Vec<u8>,
+    session_ttl_secs: u64,
+    /// Session cookies are only sent over HTTPS when it is enabled
+    secure_cookies: bool,
+    /// SHA-256 of "user:password" pairs that passed argon2 verification
+    verified: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl Auth {
+    pub fn load(config: &Config) -> Result<Auth, String> {
+        let users = match &config.auth_users_file {
+            Some(path) => load_users(path)?,
+            None => HashMap::new(),
+        };
+        let tokens = match &config.auth_tokens_file {
+            Some(path) => load_tokens(path)?,
+            None => HashMap::new(),
+        };
+        let kiosk_networks = config
+            .kiosk_networks
+            .iter()
+            .map(|network| Network::parse(network))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let enabled = config.auth_users_file.is_some() || config.auth_tokens_file.is_some();
+        // Without users nobody logs in, so no key is needed
+        let session_key = match &config.session_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None if !users.is_empty() => load_session_key()?,
+            None => Vec::new(),
+        };
+
+        Ok(Auth {
+            enabled,
+            users,
+            tokens,
+            kiosk_networks,
+            session_key,
+            session_ttl_secs: config.session_ttl_hours * 3600,
+            secure_cookies: config.tls_cert_path.is_some(),
+            verified: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Identify the sender of a request: Authorization header first, then the session cookie,
+    /// then the kiosk networks (read only). A wrong Authorization header is not overridden.
+    pub fn identify(&self, request: &Request) -> Option<Identity> {
+        if let Some(authorization) = request.header("authorization") {
+            let (scheme, credentials) = authorization.split_once(' ').unwrap_or((authorization, ""));
+            return match scheme.to_ascii_lowercase().as_str() {
+                "basic" => self.check_basic(credentials.trim()),
+                "bearer" => self.check_token(credentials.trim()),
+                _ => None,
+            };
+        }
+
+        if let Some(identity) = session_cookie(request).and_then(|session| self.check_session(session)) {
+            return Some(identity);
+        }
+
+        match request.remote_addr {
+            Some(ip) if self.kiosk_networks.iter().any(|network| network.contains(ip)) => Some(Identity {
+                name: "kiosk".to_string(),
+                scopes: vec![Scope::Read],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether a request may do what `scope` covers; like `require`, without
+    /// authentication everything but admin is allowed
+    pub fn permits(&self, request: &Request, scope: Scope) -> bool {
+        if !self.enabled {
+            return scope != Scope::Admin;
+        }
+        self.identify(request).map(|identity| identity.allows(scope)).unwrap_or(false)
+    }
+
+    /// Check a user name and password against the users file
+    pub fn login(&self, name: &str, password: &str) -> Option<Identity> {
+        let user = self.users.get(name)?;
+        let digest: [u8; 32] = Sha256::new()
+            .chain_update(name.as_bytes())
+            .chain_update(b":")
+            .chain_update(password.as_bytes())
+            .finalize()
+            .into();
+
+        let known = self.verified.lock().unwrap_or_else(|e| e.into_inner()).contains(&digest);
+        if !known {
+            // Hashes were checked when the users file was loaded
+            let hash = PasswordHash::new(&user.password_hash).ok()?;
+            Argon2::default().verify_password(password.as_bytes(), &hash).ok()?;
+
+            let mut verified = self.verified.lock().unwrap_or_else(|e| e.into_inner());
+            if verified.len() >= MAX_VERIFIED {
+                verified.clear();
+            }
+            verified.insert(digest);
+        }
+
+        Some(Identity {
+            name: name.to_string(),
+            scopes: user.scopes.clone(),
+        })
+    }
+
+    fn check_basic(&self, credentials: &str) -> Option<Identity> {
+        let decoded = String::from_utf8(STANDARD.decode(credentials).ok()?).ok()?;
+        let (name, password) = decoded.split_once(':')?;
+        self.login(name, password)
+    }
+
+    fn check_token(&self, token: &str) -> Option<Identity> {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        self.tokens.get(&digest).cloned()
+    }
+
+    /// Set-Cookie value starting a session for `identity`
+    pub fn session_cookie(&self, identity: &Identity) -> String {
+        let expires = unix_time() + self.session_ttl_secs;
+        let payload = URL_SAFE_NO_PAD.encode(format!("{}\n{}", identity.name, expires));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(payload.as_bytes()).finalize().into_bytes());
+        self.cookie(&format!("{}.{}", payload, signature), self.session_ttl_secs)
+    }
+
+    /// Set-Cookie value removing the session cookie
+    pub fn logout_cookie(&self) -> String {
+        self.cookie("", 0)
+    }
+
+    fn cookie(&self, value: &str, max_age: u64) -> String {
+        let secure = if self.secure_cookies { "; Secure" } else { "" };
+        format!("{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}", SESSION_COOKIE, value, max_age, secure)
+    }
+
+    /// Sessions are signed "user\nexpires" pairs; scopes come from the users file,
+    /// so removing a user ends their sessions on the next restart
+    fn check_session(&self, session: &str) -> Option<Identity> {
+        let (payload, signature) = session.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.mac(payload.as_bytes()).verify_slice(&signature).ok()?;
+
+        let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        let (name, expires) = payload.split_once('\n')?;
+        if expires.parse::<u64>().ok()? <= unix_time() {
+            return None;
+        }
+
+        let user = self.users.get(name)?;
+        Some(Identity {
+            name: name.to_string(),
+            scopes: user.scopes.clone(),
+        })
+    }
+
+    fn mac(&self, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.session_key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac
+    }
+}
+
+/// Wrap a handler so it only runs for requests allowed `scope`.
+/// Unknown clients get a login redirect (browsers) or 401, known ones without the scope 403.
+pub fn require<F>(scope: Scope, handler: F) -> impl Fn(&AppState, &Request, &Params) -> Response
+where
+    F: Fn(&AppState, &Request, &Params) -> Response,
+{
+    move |state, request, params| {
+        let auth = state.auth.get();
+        if !auth.is_enabled() {
+            // Without authentication everyone would be an admin
+            if scope == Scope::Admin {
+                return Response::text(403, "The admin API requires AUTH_USERS_FILE or AUTH_TOKENS_FILE");
+            }
+            return handler(state, request, params);
+        }
+
+        match auth.identify(request) {
+            Some(identity) if identity.allows(scope) => {
+                let mut response = handler(state, request, params);
+                // Shared caches must not hand protected responses to others
+                if let Some(cache_control) = response.header("cache-control").filter(|cc| cc.contains("public")) {
+                    let private = cache_control.replace("public", "private");
+                    response.set_header("Cache-Control", &private);
+                }
+                response
+            }
+            Some(_) => Response::error(403),
+            None => challenge(request),
+        }
+    }
+}
+
+/// Ask for credentials: browsers navigating to a page go to the login form, API clients get 401
+fn challenge(request: &Request) -> Response {
+    let wants_html = request.header("accept").map(|accept| accept.contains("text/html")).unwrap_or(false);
+    if matches!(request.method.as_str(), "GET" | "HEAD") && wants_html {
+        let target = if request.query_string.is_empty() {
+            request.path.clone()
+        } else {
+            format!("{}?{}", request.path, request.query_string)
+        };
+        let location = format!("/login?next={}", percent_encode(&target));
+        return Response::text(303, "Login required").with_header("Location", &location);
+    }
+
+    let bearer = request
+        .header("authorization")
+        .map(|authorization| authorization.to_ascii_lowercase().starts_with("bearer "))
+        .unwrap_or(false);
+    let challenge = if bearer {
+        format!("Bearer realm=\"{}\", error=\"invalid_token\"", REALM)
+    } else {
+        format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM)
+    };
+    Response::error(401).with_header("WWW-Authenticate", &challenge)
+}
+
+/// Hash a password for the users file
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn session_cookie(request: &Request) -> Option<&str> {
+    request
+        .header("cookie")?
+        .split([';', ','])
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// Lines of a credentials file, without blank lines and `#` comments, split at ':'
+fn read_entries(path: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, line.split(':').map(|part| part.trim().to_string()).collect()))
+        .collect())
+}
+
+fn parse_scopes(value: Option<&String>, path: &str, line: usize) -> Result<Vec<Scope>, String> {
+    match value.filter(|value| !value.is_empty()) {
+        Some(value) => value
+            .split(',')
+            .map(|name| Scope::parse(name).ok_or_else(|| format!("{}:{}: unknown scope {}", path, line, name.trim())))
+            .collect(),
+        None => Ok(vec![Scope::Read]),
+    }
+}
+
+/// Users file: `name:argon2-hash[:scopes]` per line (scopes default to read)
+fn load_users(path: &str) -> Result<HashMap<String, User>, String> {
+    let mut users = HashMap::new();
+
+    for (line, parts) in read_entries(path)? {
+        if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() {
+            return Err(format!("{}:{}: expected name:hash[:scopes]", path, line));
+        }
+        PasswordHash::new(&parts[1]).map_err(|e| format!("{}:{}: invalid password hash: {}", path, line, e))?;
+
+        let user = User {
+            password_hash: parts[1].clone(),
+            scopes: parse_scopes(parts.get(2), path, line)?,
+        };
+        users.insert(parts[0].clone(), user);
+    }
+
+    Ok(users)
+}
+
+/// Tokens file: `name:token[:scopes]` per line (scopes default to read)
+fn load_tokens(path: &str) -> Result<HashMap<[u8; 32], Identity>, String> {
+    let mut tokens = HashMap::new();
+
+    for (line, parts) in read_entries(path)? {
+        if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() || parts[1].is_empty() {
+            return Err(format!("{}:{}: expected name:token[:scopes]", path, line));
+        }
+
+        let identity = Identity {
+            name: parts[0].clone(),
+            scopes: parse_scopes(parts.get(2), path, line)?,
+        };
+        tokens.insert(Sha256::digest(parts[1].as_bytes()).into(), identity);
+    }
+
+    Ok(tokens)
+}
+
+/// Read the generated session key, creating it on first use (sessions survive restarts)
+fn load_session_key() -> Result<Vec<u8>, String> {
+    if let Ok(key) = fs::read(SESSION_KEY_FILE) {
+        if !key.is_empty() {
+            return Ok(key);
+        }
+    }
+
+    let mut key = vec![0; 32];
+    OsRng.fill_bytes(&mut key);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(SESSION_KEY_FILE)
+        .and_then(|mut file| file.write_all(&key))
+        .map_err(|e| format!("Failed to write {}: {}", SESSION_KEY_FILE, e))?;
+
+    info!("Generated session key in {}", SESSION_KEY_FILE);
+    Ok(key)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,135 @@
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc};
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+
+/// Cache-Control policies used by the route table
+pub const NO_STORE: &str = "no-store";
+/// Cache, but check with the server (ETag) before every use
+pub const REVALIDATE: &str = "no-cache";
+/// Static files that may change without their name changing
+pub const STATIC: &str = "public, max-age=300";
+/// Fingerprinted files (`app.3f9a1c2e.js`) never change under the same name
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Fingerprints are hex hashes of exactly this many characters
+const FINGERPRINT_LEN: usize = 8;
+
+/// Wrap a handler so its successful responses get `cache_control`,
+/// unless the handler set its own Cache-Control
+pub fn policy<S, F>(cache_control: &'static str, handler: F) -> impl Fn(&S, &Request, &Params) -> Response
+where
+    F: Fn(&S, &Request, &Params) -> Response,
+{
+    move |state, request, params| {
+        let mut response = handler(state, request, params);
+        let cacheable = (200..300).contains(&response.status) || response.status == 304;
+        if cacheable && response.header("cache-control").is_none() {
+            response.set_header("Cache-Control", cache_control);
+        }
+        response
+    }
+}
+
+/// ETag of a file from its modification time and size
+pub fn file_etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}.{:x}-{:x}\"", modified.as_secs(), modified.subsec_nanos(), metadata.len())
+}
+
+/// ETag from a hash (FNV-1a, 64 bit) of some content
+pub fn content_etag(parts: &[&[u8]]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        // Separator, so ["ab", "c"] and ["a", "bc"] differ
+        for byte in part.iter().chain(b"\0") {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("\"{:016x}\"", hash)
+}
+
+/// Format a time as an HTTP date ("Sun, 06 Nov 1994 08:49:37 GMT")
+pub fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether the client's copy is still current: If-None-Match is checked first,
+/// If-Modified-Since only when there is none (RFC 9110, section 13.2.2)
+pub fn is_fresh(request: &Request, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = request.header("if-none-match") {
+        // Weak comparison: W/"x" matches "x"
+        let etag = etag.trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    match (request.header("if-modified-since"), last_modified) {
+        (Some(since), Some(modified)) => match DateTime::parse_from_rfc2822(since) {
+            // HTTP dates have whole seconds
+            Ok(since) => DateTime::<Utc>::from(modified).timestamp() <= since.timestamp(),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Turn a 200 response into 304 Not Modified if the request's validators match its ETag/Last-Modified
+pub fn revalidate(request: &Request, response: Response) -> Response {
+    if response.status != 200 {
+        return response;
+    }
+    let etag = match response.header("etag") {
+        Some(etag) => etag.to_string(),
+        None => return response,
+    };
+    let last_modified = response
+        .header("last-modified")
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(SystemTime::from);
+
+    if !is_fresh(request, &etag, last_modified) {
+        return response;
+    }
+
+    let mut not_modified = not_modified(&etag);
+    // A 304 carries the headers a 200 would have sent for caching (RFC 9110, section 15.4.5)
+    for name in ["Last-Modified", "Cache-Control", "Vary", "Content-Location", "Expires"] {
+        if let Some(value) = response.header(name) {
+            not_modified.set_header(name, value);
+        }
+    }
+    not_modified
+}
+
+/// 304 with the validator; the client reuses its cached body
+pub fn not_modified(etag: &str) -> Response {
+    Response::not_modified().with_header("ETag", etag)
+}
+
+/// Whether a file name contains a content hash, like `app.3f9a1c2e.js` or `logo-3f9a1c2e.png`.
+/// Dates and numbers (`report-20240101.pdf`) are all digits, so they don't count.
+pub fn is_fingerprinted(file_name: &str) -> bool {
+    let stem = match file_name.rsplit_once('.') {
+        Some((stem, _extension)) => stem,
+        None => return false,
+    };
+    stem.rsplit(['.', '-'])
+        .next()
+        .filter(|_| stem.contains(['.', '-']))
+        .map(|hash| {
+            hash.len() == FINGERPRINT_LEN
+                && hash.chars().all(|c| c.is_ascii_hexdigit())
+                && !hash.chars().all(|c| c.is_ascii_digit())
+        })
+        .unwrap_or(false)
+}
@@ -0,0 +1,140 @@
+use std::io::Write;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use super::request::Request;
+use super::response::{Body, Response};
+use log::error;
+
+/// Smaller bodies are sent as they are; compressing them saves next to nothing
+const MIN_COMPRESS_BYTES: usize = 1024;
+
+/// Content encodings we can produce
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Preferred encoding from Accept-Encoding, honoring q-values (gzip wins ties)
+pub fn negotiate(request: &Request) -> Option<Encoding> {
+    let accept = request.header("accept-encoding")?;
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for encoding in [Encoding::Gzip, Encoding::Deflate] {
+        let q = quality(accept, encoding.name());
+        if q > 0.0 && best.map(|(_, best_q)| q > best_q).unwrap_or(true) {
+            best = Some((encoding, q));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether the client accepts `encoding` at all
+pub fn accepts(request: &Request, encoding: Encoding) -> bool {
+    request
+        .header("accept-encoding")
+        .map(|accept| quality(accept, encoding.name()) > 0.0)
+        .unwrap_or(false)
+}
+
+/// q-value of an encoding in an Accept-Encoding header ("*" applies to unlisted ones)
+fn quality(accept: &str, name: &str) -> f32 {
+    let mut wildcard = 0.0;
+
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if coding.eq_ignore_ascii_case(name) {
+            return q;
+        }
+        if coding == "*" {
+            wildcard = q;
+        }
+    }
+
+    wildcard
+}
+
+/// Whether responses of this type are worth compressing (text, not images or archives)
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || matches!(mime, "application/json" | "application/javascript" | "image/svg+xml")
+}
+
+/// Add `value` to the response's Vary header
+pub fn add_vary(response: &mut Response, value: &str) {
+    let vary = match response.header("vary") {
+        Some(existing) if existing.split(',').any(|v| v.trim().eq_ignore_ascii_case(value)) => return,
+        Some(existing) => format!("{}, {}", existing, value),
+        None => value.to_string(),
+    };
+    response.set_header("Vary", &vary);
+}
+
+/// Compress an in-memory response body if the client accepts it and it is large enough.
+/// Streamed files and streams are left alone (precompressed `.gz` files cover static files).
+pub fn compress(request: &Request, mut response: Response) -> Response {
+    let compressible = response.status == 200
+        && matches!(response.body, Body::Bytes(_))
+        && response.header("content-encoding").is_none()
+        && response.header("content-type").map(is_compressible).unwrap_or(false);
+    if !compressible {
+        return response;
+    }
+
+    // The body depends on Accept-Encoding, even when we end up not compressing it
+    add_vary(&mut response, "Accept-Encoding");
+
+    let (encoding, compressed) = match (negotiate(request), &response.body) {
+        (Some(encoding), Body::Bytes(body)) if body.len() >= MIN_COMPRESS_BYTES => match encode(encoding, body) {
+            Ok(compressed) if compressed.len() < body.len() => (encoding, compressed),
+            Ok(_) => return response,
+            Err(e) => {
+                error!("Failed to compress response: {}", e);
+                return response;
+            }
+        },
+        _ => return response,
+    };
+
+    // Same content in another encoding: the ETag becomes weak, as the bytes differ
+    if let Some(etag) = response.header("etag").filter(|etag| !etag.starts_with("W/")) {
+        let weak = format!("W/{}", etag);
+        response.set_header("ETag", &weak);
+    }
+    response.set_header("Content-Encoding", encoding.name());
+    response.body = Body::Bytes(compressed);
+    response
+}
+
+fn encode(encoding: Encoding, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        // HTTP "deflate" is the zlib format (RFC 9110, section 8.4.1.2)
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
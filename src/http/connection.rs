@@ -0,0 +1,129 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use rustls::{ServerConnection, StreamOwned};
+
+/// A client connection, plain TCP or TLS
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Connection {
+    /// The underlying socket (for timeouts and addresses)
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(stream) => &stream.sock,
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+}
+
+/// A socket whose read timeout can be changed
+pub trait ReadTimeout {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for Connection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        Connection::set_read_timeout(self, timeout)
+    }
+}
+
+impl ReadTimeout for &TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Reads one request within `limit` from its first byte.
+///
+/// The socket timeout only limits single reads, so a client sending a byte now and then
+/// could otherwise hold a worker forever.
+pub struct Deadline<'a, S: Read + ReadTimeout> {
+    reader: &'a mut BufReader<S>,
+    /// Longest wait for a single read
+    read_timeout: Duration,
+    limit: Duration,
+    /// Set once the first byte arrived
+    deadline: Option<Instant>,
+}
+
+impl<'a, S: Read + ReadTimeout> Deadline<'a, S> {
+    pub fn new(reader: &'a mut BufReader<S>, read_timeout: Duration, limit: Duration) -> Self {
+        Deadline {
+            reader,
+            read_timeout,
+            limit,
+            deadline: None,
+        }
+    }
+}
+
+impl<S: Read + ReadTimeout> Read for Deadline<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<S: Read + ReadTimeout> BufRead for Deadline<'_, S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Pipelined requests may already be buffered; the first byte starts the clock
+        if self.deadline.is_none() && !self.reader.buffer().is_empty() {
+            self.deadline = Some(Instant::now() + self.limit);
+        }
+        if self.reader.buffer().is_empty() {
+            let timeout = match self.deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => left.min(self.read_timeout),
+                    _ => return Err(io::Error::new(io::ErrorKind::TimedOut, "request took too long")),
+                },
+                None => self.read_timeout,
+            };
+            self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        }
+
+        let buffer = self.reader.fill_buf()?;
+        if self.deadline.is_none() && !buffer.is_empty() {
+            self.deadline = Some(Instant::now() + self.limit);
+        }
+        Ok(buffer)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount);
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,467 @@
+/// Tags kept in the output (everything else is dropped, keeping its text)
+const ALLOWED_TAGS: [&str; 11] = ["a", "b", "strong", "i", "em", "u", "br", "p", "ul", "ol", "li"];
+
+/// Tags whose content is dropped along with the tag
+const DROPPED_CONTENT_TAGS: [&str; 11] = [
+    "script", "style", "iframe", "object", "embed", "noscript", "template", "textarea", "title", "svg",
+    "math",
+];
+
+/// Descriptions longer than this (visible characters) are collapsed behind "more"
+const COLLAPSE_AFTER_CHARS: usize = 280;
+
+/// Descriptions with more lines than this are collapsed behind "more"
+const COLLAPSE_AFTER_LINES: usize = 6;
+
+/// Render a description (plain text or Google's HTML subset) as a `<div class="event-description">` block.
+/// Only allowlisted tags survive (links keep a safe `href`), all text is escaped,
+/// and bare URLs, email addresses and phone numbers become links.
+pub fn render_description(description: &str) -> String {
+    let mut renderer = Renderer::default();
+    renderer.render(description);
+    let body = renderer.finish();
+
+    if renderer.visible_chars > COLLAPSE_AFTER_CHARS || renderer.lines > COLLAPSE_AFTER_LINES {
+        format!(
+            r#"<div class="event-description collapsible"><div class="description-body">{}</div><button class="description-toggle" type="button" onclick="this.parentNode.classList.toggle('expanded')"></button></div>"#,
+            body
+        )
+    } else {
+        format!(r#"<div class="event-description">{}</div>"#, body)
+    }
+}
+
+#[derive(Default)]
+struct Renderer {
+    out: String,
+    /// Currently open allowlisted tags, to close them in order
+    open_tags: Vec<String>,
+    visible_chars: usize,
+    lines: usize,
+}
+
+impl Renderer {
+    fn render(&mut self, input: &str) {
+        let mut rest = input;
+
+        while let Some(lt) = rest.find('<') {
+            self.text(&rest[..lt]);
+            rest = &rest[lt..];
+
+            // Comments and doctype: skip entirely
+            if rest.starts_with("<!--") {
+                rest = match rest.find("-->") {
+                    Some(end) => &rest[end + 3..],
+                    None => "",
+                };
+                continue;
+            }
+            if rest.starts_with("<!") || rest.starts_with("<?") {
+                rest = match rest.find('>') {
+                    Some(end) => &rest[end + 1..],
+                    None => "",
+                };
+                continue;
+            }
+
+            match parse_tag(rest) {
+                Some((tag, len)) => {
+                    rest = &rest[len..];
+                    if !tag.closing && DROPPED_CONTENT_TAGS.contains(&tag.name.as_str()) {
+                        rest = skip_element_content(rest, &tag.name);
+                    } else {
+                        self.tag(tag);
+                    }
+                }
+                None => {
+                    // Not a tag, e.g. "a < b"
+                    self.text("<");
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        self.text(rest);
+    }
+
+    fn finish(&mut self) -> String {
+        while let Some(name) = self.open_tags.pop() {
+            self.out.push_str(&format!("</{}>", name));
+        }
+        std::mem::take(&mut self.out)
+    }
+
+    fn tag(&mut self, tag: Tag) {
+        if !ALLOWED_TAGS.contains(&tag.name.as_str()) {
+            return;
+        }
+
+        if tag.name == "br" {
+            self.line_break();
+            return;
+        }
+
+        if tag.closing {
+            // Close only tags we opened, including anything left open inside
+            if let Some(pos) = self.open_tags.iter().rposition(|open| *open == tag.name) {
+                while self.open_tags.len() > pos {
+                    let name = self.open_tags.pop().unwrap_or_default();
+                    self.out.push_str(&format!("</{}>", name));
+                }
+            }
+            if tag.name == "p" || tag.name == "li" {
+                self.lines += 1;
+            }
+            return;
+        }
+
+        if tag.name == "a" {
+            // Links are never nested
+            if self.open_tags.iter().any(|open| open == "a") {
+                return;
+            }
+            match tag.attribute("href").and_then(safe_href) {
+                Some(href) => self.out.push_str(&format!(
+                    r#"<a href="{}" target="_blank" rel="noopener noreferrer nofollow">"#,
+                    escape_text(&href)
+                )),
+                // Keep the text, drop the unsafe link
+                None => return,
+            }
+        } else {
+            self.out.push_str(&format!("<{}>", tag.name));
+        }
+        self.open_tags.push(tag.name);
+    }
+
+    fn text(&mut self, raw: &str) {
+        if raw.is_empty() {
+            return;
+        }
+
+        let text = decode_entities(raw);
+        let inside_link = self.open_tags.iter().any(|open| open == "a");
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if c == '\n' {
+                self.line_break();
+                i += 1;
+                continue;
+            }
+
+            if !inside_link && is_word_start(&chars, i) {
+                if let Some((len, href)) = match_link(&chars[i..]) {
+                    let label: String = chars[i..i + len].iter().collect();
+                    self.out.push_str(&format!(
+                        r#"<a href="{}" target="_blank" rel="noopener noreferrer nofollow">{}</a>"#,
+                        escape_text(&href),
+                        escape_text(&label)
+                    ));
+                    self.visible_chars += len;
+                    i += len;
+                    continue;
+                }
+            }
+
+            self.out.push_str(&escape_text(c.encode_utf8(&mut [0; 4])));
+            if !c.is_whitespace() {
+                self.visible_chars += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.out.push_str("<br>");
+        self.lines += 1;
+    }
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a tag at the start of `input`; returns the tag and its length in bytes
+fn parse_tag(input: &str) -> Option<(Tag, usize)> {
+    let bytes = input.as_bytes();
+    let mut i = 1;
+
+    let closing = bytes.get(i) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+
+    // Tag name must start with a letter
+    let name_start = i;
+    while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+        i += 1;
+    }
+    if i == name_start || !bytes[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let name = input[name_start..i].to_ascii_lowercase();
+
+    let mut attributes = Vec::new();
+    loop {
+        // Skip whitespace and self-closing slashes
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => return None,
+            Some(b'>') => break,
+            _ => {}
+        }
+
+        let key_start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let key = input[key_start..i].to_ascii_lowercase();
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = i + 1;
+                    let end = input[value_start..].find(quote as char)? + value_start;
+                    value = input[value_start..end].to_string();
+                    i = end + 1;
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = input[value_start..i].to_string();
+                }
+            }
+        }
+
+        if !key.is_empty() {
+            attributes.push((key, decode_entities(&value)));
+        }
+    }
+
+    Some((
+        Tag {
+            name,
+            closing,
+            attributes,
+        },
+        i + 1,
+    ))
+}
+
+/// Skip everything up to and including the closing tag of a dropped element
+fn skip_element_content<'a>(input: &'a str, name: &str) -> &'a str {
+    let closing = format!("</{}", name);
+    let lower = input.to_ascii_lowercase();
+    match lower.find(&closing) {
+        Some(start) => match input[start..].find('>') {
+            Some(end) => &input[start + end + 1..],
+            None => "",
+        },
+        None => "",
+    }
+}
+
+/// Allow only http(s) and mailto links
+fn safe_href(href: &str) -> Option<String> {
+    // Browsers ignore whitespace and control characters inside the scheme
+    let cleaned: String = href.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect();
+    let lower = cleaned.to_ascii_lowercase();
+
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:") {
+        Some(cleaned)
+    } else {
+        None
+    }
+}
+
+/// Decode the common named entities and numeric character references
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end + 1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, end + 2))
+        });
+
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn escape_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    i == 0 || !(chars[i - 1].is_alphanumeric() || matches!(chars[i - 1], '@' | '.' | '/' | '+' | '-' | '_'))
+}
+
+/// Match a bare URL, email address or phone number; returns its length and href
+fn match_link(chars: &[char]) -> Option<(usize, String)> {
+    match_url(chars)
+        .or_else(|| match_email(chars))
+        .or_else(|| match_phone(chars))
+}
+
+fn match_url(chars: &[char]) -> Option<(usize, String)> {
+    let head: String = chars.iter().take(8).collect::<String>().to_ascii_lowercase();
+    let prefix = if head.starts_with("https://") || head.starts_with("http://") {
+        ""
+    } else if head.starts_with("www.") {
+        "https://"
+    } else {
+        return None;
+    };
+
+    let mut len = chars
+        .iter()
+        .position(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+        .unwrap_or(chars.len());
+    // Trailing punctuation usually belongs to the sentence
+    while len > 0 && matches!(chars[len - 1], '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']') {
+        len -= 1;
+    }
+
+    let url: String = chars[..len].iter().collect();
+    if url.len() <= "https://".len() {
+        return None;
+    }
+    Some((len, format!("{}{}", prefix, url)))
+}
+
+fn match_email(chars: &[char]) -> Option<(usize, String)> {
+    let is_local = |c: &char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-');
+    let is_domain = |c: &char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-');
+
+    let local_len = chars.iter().take_while(|c| is_local(c)).count();
+    if local_len == 0 || chars.get(local_len) != Some(&'@') {
+        return None;
+    }
+
+    let domain_start = local_len + 1;
+    let mut domain_len = chars[domain_start..].iter().take_while(|c| is_domain(c)).count();
+    while domain_len > 0 && matches!(chars[domain_start + domain_len - 1], '.' | '-') {
+        domain_len -= 1;
+    }
+
+    let domain: String = chars[domain_start..domain_start + domain_len].iter().collect();
+    let tld = domain.rsplit('.').next().unwrap_or("");
+    if !domain.contains('.') || tld.len() < 2 || !tld.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let len = domain_start + domain_len;
+    let email: String = chars[..len].iter().collect();
+    Some((len, format!("mailto:{}", email)))
+}
+
+fn match_phone(chars: &[char]) -> Option<(usize, String)> {
+    // Only numbers that look international or local ("+36 ...", "(06) ...", "030 ..."),
+    // so dates and times are not linked
+    match chars.first() {
+        Some('+') | Some('(') | Some('0') => {}
+        _ => return None,
+    }
+
+    let mut len = chars
+        .iter()
+        .take_while(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')' | '.' | '/'))
+        .count();
+    while len > 0 && !chars[len - 1].is_ascii_digit() {
+        len -= 1;
+    }
+    if chars.get(len).map(|c| c.is_alphanumeric()).unwrap_or(false) || starts_with_date(&chars[..len]) {
+        return None;
+    }
+
+    let number: String = chars[..len]
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| c.is_ascii_digit() || (**c == '+' && *i == 0))
+        .map(|(_, c)| *c)
+        .collect();
+    let digits = number.chars().filter(|c| c.is_ascii_digit()).count();
+    if !(8..=15).contains(&digits) {
+        return None;
+    }
+
+    Some((len, format!("tel:{}", number)))
+}
+
+/// "01.02.2025" or "1/2/25" (day, month and year with the same separator)
+fn starts_with_date(chars: &[char]) -> bool {
+    let digits_at = |start: usize| chars[start.min(chars.len())..].iter().take_while(|c| c.is_ascii_digit()).count();
+
+    let day = digits_at(0);
+    let separator = match chars.get(day) {
+        Some(c @ ('.' | '/')) if (1..=2).contains(&day) => *c,
+        _ => return false,
+    };
+    let month = digits_at(day + 1);
+    if !(1..=2).contains(&month) || chars.get(day + 1 + month) != Some(&separator) {
+        return false;
+    }
+    let year = digits_at(day + month + 2);
+    (2..=4).contains(&year)
+}
@@ -1,43 +1,143 @@
-use super::html::Html;
-use std::fs;
-use std::path::Path;
-
-pub fn serve_file(path: &str) -> Result<Html, std::io::Error> {
-    // Convert URL path to file path
-    let file_path = if path == "/" {
-        "public/index.html"
-    } else {
-        // Remove leading slash and prepend 'public/'
-        &format!("public{}", path)
+use super::cache;
+use super::compression::{self, Encoding};
+use super::request::Request;
+use super::response::{Body, Response};
+use super::router::Params;
+use super::state::AppState;
+use super::url::percent_decode;
+use std::fs::{self, File, Metadata};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use log::{debug, warn};
+
+/// Directory static files are served from
+const STATIC_ROOT: &str = "public";
+
+/// GET /* - static files from public/ (304 if the client's copy is current)
+pub fn static_files(_state: &AppState, request: &Request, _params: &Params) -> Response {
+    let gzip = compression::accepts(request, Encoding::Gzip);
+    match serve_file(&request.path, gzip) {
+        Ok(response) => cache::revalidate(request, response),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            warn!("Refused static path {}: {}", request.path, e);
+            Response::error(403)
+        }
+        Err(e) => {
+            debug!("{}", e);
+            Response::error(404)
+        }
+    }
+}
+
+/// Serve a file below STATIC_ROOT for a (still percent-encoded) URL path,
+/// with ETag and Last-Modified. With `gzip`, a precompressed `<file>.gz` next to it is sent instead.
+/// Errors with NotFound for missing or hidden files and PermissionDenied for paths leaving the root.
+pub fn serve_file(path: &str, gzip: bool) -> Result<Response, Error> {
+    let root = fs::canonicalize(STATIC_ROOT)?;
+    let mut file_path = resolve_path(&root, path)?;
+
+    // Directories serve their index.html
+    if file_path.is_dir() {
+        file_path = file_path.join("index.html");
+    }
+
+    // Resolve symlinks and make sure we're still inside the root
+    let file_path = fs::canonicalize(&file_path)
+        .map_err(|_| Error::new(ErrorKind::NotFound, "Not found"))?;
+    if !file_path.starts_with(&root) {
+        return Err(Error::new(ErrorKind::PermissionDenied, "Path escapes static root"));
+    }
+
+    // Open file; the body is streamed, so files of any size work
+    let file = File::open(&file_path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(Error::new(ErrorKind::NotFound, "Not a file"));
+    }
+
+    let precompressed = precompressed_file(&root, &file_path);
+    let has_variants = precompressed.is_some();
+    let (file, metadata, encoding) = match precompressed {
+        Some((gz_file, gz_metadata)) if gzip => (gz_file, gz_metadata, Some(Encoding::Gzip)),
+        _ => (file, metadata, None),
     };
 
-    // Read file
-    match fs::read(file_path) {
-        Ok(contents) => {
-            let content_type: String = get_content_type(file_path).parse().unwrap();
-            let content: String = String::from_utf8_lossy(&contents).parse().unwrap();
-            Ok(Html {
-                content_type,
-                content,
-            })
+    let etag = cache::file_etag(&metadata);
+    let mut response = Response::new(
+        200,
+        get_content_type(&file_path),
+        Body::File(file, metadata.len()),
+    )
+    .with_header("ETag", &etag);
+
+    if let Some(encoding) = encoding {
+        response.set_header("Content-Encoding", encoding.name());
+    }
+    if has_variants {
+        compression::add_vary(&mut response, "Accept-Encoding");
+    }
+
+    if let Ok(modified) = metadata.modified() {
+        response.set_header("Last-Modified", &cache::http_date(modified));
+    }
+
+    // Fingerprinted names change with their content, so they can be cached forever
+    let file_name = file_path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    if cache::is_fingerprinted(file_name) {
+        response.set_header("Cache-Control", cache::IMMUTABLE);
+    }
+
+    Ok(response)
+}
+
+/// `<file>.gz` next to a file, if it exists and stays inside the root
+fn precompressed_file(root: &Path, file_path: &Path) -> Option<(File, Metadata)> {
+    let mut gz_path = file_path.as_os_str().to_owned();
+    gz_path.push(".gz");
+
+    let gz_path = fs::canonicalize(gz_path).ok().filter(|path| path.starts_with(root))?;
+    let file = File::open(gz_path).ok()?;
+    let metadata = file.metadata().ok().filter(|metadata| metadata.is_file())?;
+    Some((file, metadata))
+}
+
+/// Map a URL path onto the root, rejecting traversal and dotfiles
+fn resolve_path(root: &Path, url_path: &str) -> Result<PathBuf, Error> {
+    // Decode first, so "%2e%2e" and "%2f" are checked like their plain forms
+    let decoded = percent_decode(url_path);
+    if decoded.contains('\0') || decoded.contains('\\') {
+        return Err(Error::new(ErrorKind::PermissionDenied, "Invalid characters in path"));
+    }
+
+    let mut file_path = root.to_path_buf();
+    for segment in decoded.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        if segment == ".." {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Path traversal"));
+        }
+        // Hidden files (.env, .git, ...) are never served
+        if segment.starts_with('.') {
+            return Err(Error::new(ErrorKind::NotFound, "Not found"));
         }
-        Err(_) => Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Not found",
-        )),
+        file_path.push(segment);
     }
+
+    Ok(file_path)
 }
 
-fn get_content_type(path: &str) -> &str {
-    let path_obj = Path::new(path);
-    match path_obj.extension().and_then(|s| s.to_str()) {
-        Some("html") => "text/html",
-        Some("css") => "text/css",
+fn get_content_type(path: &Path) -> &str {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
         Some("js") => "application/javascript",
         Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
         Some("png") => "image/png",
         Some("jpg") | Some("jpeg") => "image/jpeg",
         Some("gif") => "image/gif",
-        _ => "text/plain",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
     }
 }
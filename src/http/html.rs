@@ -1,4 +0,0 @@
-pub struct Html {
-    pub content_type: String,
-    pub content: String,
-}
@@ -0,0 +1,295 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::sse::StreamSlot;
+use super::state::AppState;
+use super::auth::Scope;
+use super::websocket::{self, Message, WebSocket, WebSocketError};
+use crate::calendar::{resolve_calendars, EventStore, UpdateNotifier};
+use crate::config::Config;
+use crate::shared::Shared;
+use log::{debug, error, info, warn};
+
+/// Views a kiosk can be switched to
+const VIEWS: [&str; 4] = ["agenda", "day", "week", "next"];
+
+/// Acknowledged reminders remembered (oldest are forgotten first)
+const MAX_ACKNOWLEDGED: usize = 100;
+
+/// How often the connection checks for updates to push while the client is quiet
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A ping is sent after this much silence; without any frame for three times as long the client is gone
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What all kiosk screens show, changed by `command` messages
+#[derive(Debug, Clone, Serialize)]
+pub struct DisplayState {
+    pub view: String,
+    /// Calendar id to show, None for all calendars
+    pub calendar: Option<String>,
+    /// Event ids whose reminder was acknowledged
+    pub acknowledged: Vec<String>,
+}
+
+/// Display state shared by all connections
+pub struct Display {
+    state: Mutex<DisplayState>,
+    /// Signalled whenever the state changes
+    pub changes: UpdateNotifier,
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            state: Mutex::new(DisplayState {
+                view: VIEWS[0].to_string(),
+                calendar: None,
+                acknowledged: Vec::new(),
+            }),
+            changes: UpdateNotifier::new(),
+        }
+    }
+
+    pub fn state(&self) -> DisplayState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut DisplayState)) {
+        change(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+        self.changes.notify();
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+/// Messages from the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Receive `event` messages for these topics ("events", "display")
+    Subscribe { topics: Vec<String> },
+    Command {
+        #[serde(flatten)]
+        command: Command,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    SetView { view: String },
+    SetCalendar { calendar: Option<String> },
+    AckReminder { event_id: String },
+}
+
+/// Messages to the client
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Subscribed { topics: &'a [String] },
+    Event { topic: &'a str, data: serde_json::Value },
+    Error { message: String },
+}
+
+const TOPICS: [&str; 2] = ["events", "display"];
+
+/// GET /api/ws - WebSocket for kiosk screens (see README, "Kiosk WebSocket")
+pub fn connect(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let slot = match StreamSlot::acquire() {
+        Some(slot) => slot,
+        None => {
+            warn!("Too many open streams, rejecting WebSocket");
+            return Response::error(503).with_header("Retry-After", "5");
+        }
+    };
+
+    let updates = Arc::clone(&state.updates);
+    let events = Arc::clone(&state.events);
+    let display = Arc::clone(&state.display);
+    let config = Arc::clone(&state.config);
+    // Watching needs read, changing what every screen shows needs control
+    let may_command = state.auth.get().permits(request, Scope::Control);
+
+    websocket::accept(request, move |mut socket| {
+        let _slot = slot;
+        let mut session = Session {
+            updates: &updates,
+            events: &events,
+            display: &display,
+            config: &config,
+            may_command,
+            topics: Vec::new(),
+            events_version: updates.version(),
+            display_version: display.changes.version(),
+        };
+
+        match session.run(&mut socket) {
+            Err(WebSocketError::Closed) | Ok(()) => debug!("WebSocket closed"),
+            Err(e) => info!("WebSocket closed: {}", e),
+        }
+    })
+}
+
+/// One kiosk connection
+struct Session<'a> {
+    updates: &'a UpdateNotifier,
+    events: &'a EventStore,
+    display: &'a Display,
+    /// Read per command, so a reload applies to open connections
+    config: &'a Shared<Config>,
+    may_command: bool,
+    topics: Vec<String>,
+    /// Versions already pushed to this client
+    events_version: u64,
+    display_version: u64,
+}
+
+impl Session<'_> {
+    fn run(&mut self, socket: &mut WebSocket) -> Result<(), WebSocketError> {
+        let mut last_seen = Instant::now();
+        let mut last_ping = Instant::now();
+
+        loop {
+            if socket.wait_readable(POLL_INTERVAL)? {
+                last_seen = Instant::now();
+                match socket.read_message()? {
+                    Message::Text(text) => self.handle_text(socket, &text)?,
+                    Message::Binary(data) => {
+                        info!("Rejecting {} byte binary WebSocket message", data.len());
+                        socket.close(websocket::CLOSE_UNSUPPORTED_DATA, "only text messages are supported")?;
+                        return Ok(());
+                    }
+                }
+            }
+
+            self.push_changes(socket)?;
+
+            if last_seen.elapsed() > PING_INTERVAL * 3 {
+                socket.close(websocket::CLOSE_GOING_AWAY, "idle")?;
+                return Ok(());
+            }
+            if last_seen.elapsed() > PING_INTERVAL && last_ping.elapsed() > PING_INTERVAL {
+                socket.send_ping()?;
+                last_ping = Instant::now();
+            }
+        }
+    }
+
+    fn handle_text(&mut self, socket: &mut WebSocket, text: &str) -> Result<(), WebSocketError> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => return send(socket, &ServerMessage::Error { message: format!("Invalid message: {}", e) }),
+        };
+
+        match message {
+            ClientMessage::Subscribe { topics } => {
+                if let Some(unknown) = topics.iter().find(|topic| !TOPICS.contains(&topic.as_str())) {
+                    let message = format!("Unknown topic '{}' (expected: {})", unknown, TOPICS.join(", "));
+                    return send(socket, &ServerMessage::Error { message });
+                }
+                send(socket, &ServerMessage::Subscribed { topics: &topics })?;
+
+                // New subscribers start with the current state
+                for topic in &topics {
+                    send(socket, &self.event_message(topic))?;
+                }
+                self.topics = topics;
+                Ok(())
+            }
+            ClientMessage::Command { command } => match self.apply(command) {
+                // Success is visible as a "display" event (to this client too, if subscribed)
+                Ok(()) => Ok(()),
+                Err(message) => send(socket, &ServerMessage::Error { message }),
+            },
+        }
+    }
+
+    /// Validate a command and change the shared display state
+    fn apply(&self, command: Command) -> Result<(), String> {
+        if !self.may_command {
+            return Err("Commands need the control scope".to_string());
+        }
+        match command {
+            Command::SetView { view } => {
+                if !VIEWS.contains(&view.as_str()) {
+                    return Err(format!("Unknown view '{}' (expected: {})", view, VIEWS.join(", ")));
+                }
+                self.display.update(|state| state.view = view);
+            }
+            Command::SetCalendar { calendar } => {
+                if let Some(id) = &calendar {
+                    let calendars = resolve_calendars(&self.config.get(), self.events.snapshot().calendars());
+                    if !calendars.iter().any(|c| &c.id == id) {
+                        return Err(format!("Unknown calendar '{}'", id));
+                    }
+                }
+                self.display.update(|state| state.calendar = calendar);
+            }
+            Command::AckReminder { event_id } => {
+                if self.events.snapshot().find(&event_id).is_none() {
+                    return Err(format!("Unknown event '{}'", event_id));
+                }
+                self.display.update(|state| {
+                    if !state.acknowledged.contains(&event_id) {
+                        state.acknowledged.push(event_id);
+                    }
+                    let excess = state.acknowledged.len().saturating_sub(MAX_ACKNOWLEDGED);
+                    state.acknowledged.drain(..excess);
+                });
+            }
+        }
+        // Agenda pages render the display state and refresh on updates
+        self.updates.notify();
+        Ok(())
+    }
+
+    /// Send an event for every subscribed topic that changed since the last push
+    fn push_changes(&mut self, socket: &mut WebSocket) -> Result<(), WebSocketError> {
+        let events_version = self.updates.version();
+        if events_version != self.events_version {
+            self.events_version = events_version;
+            if self.subscribed("events") {
+                send(socket, &self.event_message("events"))?;
+            }
+        }
+
+        let display_version = self.display.changes.version();
+        if display_version != self.display_version {
+            self.display_version = display_version;
+            if self.subscribed("display") {
+                send(socket, &self.event_message("display"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribed(&self, topic: &str) -> bool {
+        self.topics.iter().any(|t| t == topic)
+    }
+
+    fn event_message(&self, topic: &str) -> ServerMessage<'static> {
+        let (topic, data) = match topic {
+            "display" => ("display", serde_json::json!(self.display.state())),
+            _ => ("events", serde_json::json!({ "last_update": self.events.last_update() })),
+        };
+        ServerMessage::Event { topic, data }
+    }
+}
+
+fn send(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), WebSocketError> {
+    match serde_json::to_string(message) {
+        Ok(json) => socket.send_text(&json),
+        Err(e) => {
+            error!("Failed to serialize message: {}", e);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use super::agenda::escape_html;
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::state::AppState;
+use super::url::parse_query;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+/// Failed logins a client gets before it has to wait
+const FREE_FAILURES: u32 = 5;
+
+/// Longest wait between attempts; the wait doubles with every failure up to this
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Clients remembered (all are forgotten when more fail)
+const MAX_CLIENTS: usize = 10_000;
+
+/// Failed logins per client address. Every attempt runs argon2, so guessing
+/// is slowed down before it reaches the password check.
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<Option<IpAddr>, Failures>>,
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    /// How long after the last failure the next attempt is allowed
+    fn backoff(&self) -> Duration {
+        match self.count.checked_sub(FREE_FAILURES) {
+            Some(over) => Duration::from_secs(1 << over.min(10)).min(MAX_BACKOFF),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+impl LoginThrottle {
+    pub fn new() -> LoginThrottle {
+        LoginThrottle {
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long a client still has to wait before its next attempt, if at all
+    pub fn retry_after(&self, client: Option<IpAddr>) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = failures.get(&client)?;
+        entry.backoff().checked_sub(entry.last.elapsed()).filter(|wait| !wait.is_zero())
+    }
+
+    pub fn failed(&self, client: Option<IpAddr>) {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() >= MAX_CLIENTS && !failures.contains_key(&client) {
+            // Clients that waited out the longest backoff start over anyway
+            failures.retain(|_, entry| entry.last.elapsed() < MAX_BACKOFF);
+            if failures.len() >= MAX_CLIENTS {
+                failures.clear();
+            }
+        }
+        let entry = failures.entry(client).or_insert(Failures { count: 0, last: Instant::now() });
+        entry.count = entry.count.saturating_add(1);
+        entry.last = Instant::now();
+    }
+
+    pub fn succeeded(&self, client: Option<IpAddr>) {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).remove(&client);
+    }
+}
+
+impl Default for LoginThrottle {
+    fn default() -> LoginThrottle {
+        LoginThrottle::new()
+    }
+}
+
+/// GET /login - the login form (`?next=/path` is where to go afterwards)
+pub fn form(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let next = safe_next(request.query.get("next").map(|next| next.as_str()));
+    if !state.auth.get().is_enabled() {
+        return Response::text(303, "Authentication is disabled").with_header("Location", next);
+    }
+    login_page(200, next, None)
+}
+
+/// POST /login - check the form and start a session cookie (429 after repeated failures)
+pub fn login(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let form = parse_query(&String::from_utf8_lossy(&request.body));
+    let next = safe_next(form.get("next").map(|next| next.as_str()));
+    let client = request.remote_addr;
+
+    if let Some(wait) = state.logins.retry_after(client) {
+        let seconds = wait.as_secs() + 1;
+        warn!("Throttled login from {:?} for {}s", client, seconds);
+        let message = format!("Too many failed logins, try again in {} seconds", seconds);
+        return login_page(429, next, Some(&message)).with_header("Retry-After", &seconds.to_string());
+    }
+    let name = form.get("username").map(|name| name.trim()).unwrap_or("");
+    let password = form.get("password").map(|password| password.as_str()).unwrap_or("");
+
+    let auth = state.auth.get();
+    match auth.login(name, password) {
+        Some(identity) => {
+            state.logins.succeeded(client);
+            info!("Login: {}", identity.name);
+            Response::text(303, "Logged in")
+                .with_header("Location", next)
+                .with_header("Set-Cookie", &auth.session_cookie(&identity))
+        }
+        None => {
+            state.logins.failed(client);
+            warn!("Failed login for {:?}", name);
+            login_page(401, next, Some("Wrong user name or password"))
+        }
+    }
+}
+
+/// POST /logout - drop the session cookie
+pub fn logout(state: &AppState, _request: &Request, _params: &Params) -> Response {
+    Response::text(303, "Logged out")
+        .with_header("Location", "/login")
+        .with_header("Set-Cookie", &state.auth.get().logout_cookie())
+}
+
+/// Only local paths, so the login can't be used to send people elsewhere ("//evil.example").
+/// `next` is already decoded; browsers drop tabs and line breaks ("/\t/evil.example"),
+/// so any control character or whitespace is refused.
+fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next)
+            if next.starts_with('/')
+                && !next.starts_with("//")
+                && !next.contains('\\')
+                && !next.chars().any(|c| c.is_control() || c.is_whitespace()) =>
+        {
+            next
+        }
+        _ => "/",
+    }
+}
+
+fn login_page(status: u16, next: &str, error: Option<&str>) -> Response {
+    let error = error
+        .map(|error| format!("        <p class=\"error\">{}</p>\n", escape_html(error)))
+        .unwrap_or_default();
+
+    let html = format!(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>MY AGENDA - LOGIN</title>
+    <style>
+        * {{
+            margin: 0;
+            padding: 0;
+            box-sizing: border-box;
+        }}
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
+            background-color: #000;
+            color: #fff;
+            padding: 40px 20px;
+            max-width: 360px;
+            margin: 0 auto;
+        }}
+        .header {{
+            text-align: right;
+            font-size: 16px;
+            letter-spacing: 2px;
+            color: #999;
+            margin-bottom: 40px;
+        }}
+        label {{
+            display: block;
+            color: #999;
+            font-size: 14px;
+            margin-bottom: 6px;
+        }}
+        input {{
+            width: 100%;
+            padding: 10px;
+            margin-bottom: 20px;
+            background-color: #111;
+            color: #fff;
+            border: 1px solid #333;
+            border-radius: 4px;
+            font-size: 16px;
+        }}
+        button {{
+            width: 100%;
+            padding: 10px;
+            background-color: #ff6b35;
+            color: #fff;
+            border: none;
+            border-radius: 4px;
+            font-size: 16px;
+            cursor: pointer;
+        }}
+        .error {{
+            color: #ff6b35;
+            margin-bottom: 20px;
+        }}
+    </style>
+</head>
+<body>
+    <div class="header">MY AGENDA</div>
+    <form method="post" action="/login">
+{}        <input type="hidden" name="next" value="{}">
+        <label for="username">User name</label>
+        <input id="username" name="username" autocomplete="username" required autofocus>
+        <label for="password">Password</label>
+        <input id="password" name="password" type="password" autocomplete="current-password" required>
+        <button type="submit">Log in</button>
+    </form>
+</body></html>"#,
+        error,
+        escape_html(next)
+    );
+
+    let mut response = Response::html(html);
+    response.status = status;
+    response.with_header("Cache-Control", "no-store")
+}
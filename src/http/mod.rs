@@ -1,7 +1,27 @@
-pub mod html;
+pub mod agenda;
+pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod compression;
+pub mod connection;
+pub mod description;
+pub mod pool;
+pub mod redirect;
+pub mod request;
 pub mod response;
+pub mod router;
+pub mod routes;
 pub mod file_server;
+pub mod kiosk;
+pub mod login;
 pub mod server;
+pub mod share;
+pub mod sse;
+pub mod state;
+pub mod tls;
+pub mod url;
+pub mod websocket;
 
 // Re-export only what's used externally
 pub use server::serve;
+pub use state::AppState;
@@ -0,0 +1,92 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use crate::signals::join_until;
+use log::error;
+
+/// Fixed number of worker threads handling items (connections) from a bounded queue
+pub struct WorkerPool<T: Send + 'static> {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<SyncSender<T>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Start `size` workers; at most `queue_limit` items wait for a free worker
+    pub fn new<F>(size: usize, queue_limit: usize, handler: F) -> WorkerPool<T>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(queue_limit);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..size.max(1))
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+                thread::Builder::new()
+                    .name(format!("http-worker-{}", id))
+                    .spawn(move || worker_loop(id, receiver, handler))
+                    .expect("Failed to spawn worker thread")
+            })
+            .collect();
+
+        WorkerPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    /// Queue an item, or give it back if the queue is full
+    pub fn try_submit(&self, item: T) -> Result<(), T> {
+        match &self.sender {
+            Some(sender) => sender.try_send(item).map_err(|e| match e {
+                TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+            }),
+            None => Err(item),
+        }
+    }
+
+    /// Stop taking items and let the workers finish the queue until `deadline`;
+    /// false if some were still busy (they are left running)
+    pub fn shutdown(mut self, deadline: Instant) -> bool {
+        drop(self.sender.take());
+        // Workers not joined by the deadline are detached when the rest is dropped
+        self.workers.drain(..).all(|worker| join_until(worker, deadline))
+    }
+}
+
+impl<T: Send + 'static> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        // Closing the channel lets workers finish the queue and exit
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop<T, F>(id: usize, receiver: Arc<Mutex<Receiver<T>>>, handler: Arc<F>)
+where
+    F: Fn(T) + Send + Sync + 'static,
+{
+    loop {
+        // Hold the lock only while waiting for the next item
+        let item = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(poisoned) => poisoned.into_inner().recv(),
+        };
+
+        let item = match item {
+            Ok(item) => item,
+            Err(_) => break, // Pool dropped
+        };
+
+        // A panic while handling one connection must not kill the worker
+        if panic::catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+            error!("Worker {}: connection handler panicked", id);
+        }
+    }
+}
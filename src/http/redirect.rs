@@ -0,0 +1,93 @@
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use super::connection::Deadline;
+use super::pool::WorkerPool;
+use super::request::read_request;
+use super::response::Response;
+use log::info;
+
+/// Redirects are tiny; a few workers are plenty
+const REDIRECT_WORKERS: usize = 2;
+const REDIRECT_QUEUE_LIMIT: usize = 16;
+
+/// Clients get this long to send their request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Listen on `addr` in the background and answer every request with a redirect to HTTPS on `https_port`
+pub fn start_redirect_listener(addr: &str, https_port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format!("Failed to bind HTTP redirect listener on {}: {}", addr, e))?;
+    info!("Redirecting http://{} to HTTPS (port {})", addr, https_port);
+
+    thread::spawn(move || {
+        let pool = WorkerPool::new(REDIRECT_WORKERS, REDIRECT_QUEUE_LIMIT, move |stream| {
+            redirect_connection(stream, https_port);
+        });
+
+        for stream in listener.incoming().flatten() {
+            // Busy: the client can simply retry
+            let _ = pool.try_submit(stream);
+        }
+    });
+    Ok(())
+}
+
+fn redirect_connection(mut stream: TcpStream, https_port: u16) {
+    if stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .is_err()
+    {
+        return;
+    }
+
+    let request = match read_request(&mut Deadline::new(&mut BufReader::new(&stream), TIMEOUT, TIMEOUT)) {
+        Ok(request) => request,
+        Err(e) => {
+            if let Some((status, _)) = e.status() {
+                let _ = Response::error(status).with_header("Connection", "close").write_to(&mut stream);
+            }
+            return;
+        }
+    };
+
+    let response = match request.header("host").and_then(host_name) {
+        Some(host) => {
+            let port = if https_port == 443 { String::new() } else { format!(":{}", https_port) };
+            let target = format!("https://{}{}{}", host, port, request_target(&request.path, &request.query_string));
+            Response::text(301, &format!("Moved to {}", target)).with_header("Location", &target)
+        }
+        None => Response::text(400, "Missing or invalid Host header"),
+    };
+
+    let _ = response.with_header("Connection", "close").write_to(&mut stream);
+}
+
+/// Host without port; None if it contains anything but host name characters
+fn host_name(host: &str) -> Option<&str> {
+    // IPv6 literals keep their brackets: "[::1]:8080" -> "[::1]"
+    let name = match host.strip_prefix('[') {
+        Some(rest) => &host[..rest.find(']')? + 2],
+        None => host.split(':').next().unwrap_or(""),
+    };
+
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '[' | ']' | ':'));
+    if valid {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn request_target(path: &str, query: &str) -> String {
+    if query.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, query)
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, Read};
+use std::net::IpAddr;
+use super::url::{parse_query, split_target};
+
+/// Request line plus headers may not exceed this (431 otherwise)
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// Request bodies may not exceed this (413 otherwise)
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Each chunk size line of a chunked body (with extensions) may not exceed this;
+/// the number of chunks is already bounded by the body limit
+const MAX_CHUNK_LINE_BYTES: usize = 1024;
+
+/// A parsed HTTP/1.x request
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string, still percent-encoded
+    pub path: String,
+    /// Decoded query parameters
+    pub query: HashMap<String, String>,
+    /// Query string as sent (without '?')
+    pub query_string: String,
+    /// e.g. "HTTP/1.1"
+    pub version: String,
+    /// Header names are lowercase; repeated headers are joined with ", "
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    /// Client address, set by the server
+    pub remote_addr: Option<IpAddr>,
+}
+
+impl Request {
+    /// Look up a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(|v| v.as_str())
+    }
+}
+
+impl Display for Request {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.method, self.path, self.version)?;
+        if !self.body.is_empty() {
+            write!(f, " ({} byte body)", self.body.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// Client closed the connection before sending a request
+    Closed,
+    Io(io::Error),
+    BadRequest(&'static str),
+    /// The request wasn't complete in time
+    Timeout,
+    HeadersTooLarge,
+    PayloadTooLarge,
+    UnsupportedVersion,
+    UnsupportedTransferEncoding,
+}
+
+impl RequestError {
+    /// Status code and reason to answer with (None = just drop the connection)
+    pub fn status(&self) -> Option<(u16, &'static str)> {
+        match self {
+            RequestError::Closed | RequestError::Io(_) => None,
+            RequestError::BadRequest(_) => Some((400, "Bad Request")),
+            RequestError::Timeout => Some((408, "Request Timeout")),
+            RequestError::PayloadTooLarge => Some((413, "Payload Too Large")),
+            RequestError::HeadersTooLarge => Some((431, "Request Header Fields Too Large")),
+            RequestError::UnsupportedTransferEncoding => Some((501, "Not Implemented")),
+            RequestError::UnsupportedVersion => Some((505, "HTTP Version Not Supported")),
+        }
+    }
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Closed => write!(f, "connection closed"),
+            RequestError::Io(e) => write!(f, "{}", e),
+            RequestError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            RequestError::Timeout => write!(f, "request timed out"),
+            RequestError::HeadersTooLarge => write!(f, "request headers too large"),
+            RequestError::PayloadTooLarge => write!(f, "request body too large"),
+            RequestError::UnsupportedVersion => write!(f, "unsupported HTTP version"),
+            RequestError::UnsupportedTransferEncoding => write!(f, "unsupported transfer encoding"),
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        // Socket read timeouts are WouldBlock on Unix and TimedOut on Windows
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RequestError::Timeout,
+            _ => RequestError::Io(e),
+        }
+    }
+}
+
+/// Read one request (head and body) from a buffered stream
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, RequestError> {
+    let mut head_bytes = 0;
+
+    // Request line (empty lines before it are allowed)
+    let request_line = loop {
+        match read_head_line(reader, &mut head_bytes)? {
+            None => return Err(RequestError::Closed),
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+
+    let parts: Vec<&str> = request_line.split(' ').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.is_empty()) {
+        return Err(RequestError::BadRequest("malformed request line"));
+    }
+    let (method, target, version) = (parts[0], parts[1], parts[2]);
+
+    if !method.bytes().all(is_token_byte) {
+        return Err(RequestError::BadRequest("invalid method"));
+    }
+    if !version.starts_with("HTTP/") {
+        return Err(RequestError::BadRequest("invalid version"));
+    }
+    if version != "HTTP/1.1" && version != "HTTP/1.0" {
+        return Err(RequestError::UnsupportedVersion);
+    }
+    if !target.starts_with('/') && target != "*" {
+        return Err(RequestError::BadRequest("invalid request target"));
+    }
+
+    // Headers until the empty line
+    let mut headers: HashMap<String, String> = HashMap::new();
+    loop {
+        let line = read_head_line(reader, &mut head_bytes)?
+            .ok_or(RequestError::BadRequest("unexpected end of headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            return Err(RequestError::BadRequest("obsolete header folding"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(RequestError::BadRequest("malformed header"))?;
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(RequestError::BadRequest("invalid header name"));
+        }
+
+        let name = name.to_ascii_lowercase();
+        let value = value.trim();
+        headers
+            .entry(name)
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    let body = read_body(reader, &headers)?;
+    let (path, query) = split_target(target);
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: parse_query(query),
+        query_string: query.to_string(),
+        version: version.to_string(),
+        headers,
+        body,
+        remote_addr: None,
+    })
+}
+
+/// Read one CRLF (or LF) terminated line of the request head, enforcing the size cap.
+/// Returns None on end of stream before any byte.
+fn read_head_line<R: BufRead>(reader: &mut R, head_bytes: &mut usize) -> Result<Option<String>, RequestError> {
+    let remaining = MAX_HEADER_BYTES.saturating_sub(*head_bytes);
+    let mut line = Vec::new();
+    let read = reader.by_ref().take(remaining as u64 + 1).read_until(b'\n', &mut line)?;
+    *head_bytes += read;
+
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return if *head_bytes > MAX_HEADER_BYTES {
+            Err(RequestError::HeadersTooLarge)
+        } else {
+            Err(RequestError::BadRequest("unexpected end of request"))
+        };
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| RequestError::BadRequest("invalid UTF-8 in request head"))
+}
+
+fn read_body<R: BufRead>(reader: &mut R, headers: &HashMap<String, String>) -> Result<Vec<u8>, RequestError> {
+    let content_length = headers.get("content-length");
+
+    if let Some(encoding) = headers.get("transfer-encoding") {
+        // Both framing headers at once is a request smuggling attempt
+        if content_length.is_some() {
+            return Err(RequestError::BadRequest("both Content-Length and Transfer-Encoding"));
+        }
+        if !encoding.eq_ignore_ascii_case("chunked") {
+            return Err(RequestError::UnsupportedTransferEncoding);
+        }
+        return read_chunked_body(reader);
+    }
+
+    let length = match content_length {
+        None => return Ok(Vec::new()),
+        Some(value) => {
+            // Repeated headers were joined; they must all agree
+            let mut values = value.split(',').map(|v| v.trim());
+            let first = values.next().unwrap_or("");
+            if values.any(|v| v != first) {
+                return Err(RequestError::BadRequest("conflicting Content-Length"));
+            }
+            // Digits only: `parse` would also take a sign ("+5")
+            if first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(RequestError::BadRequest("invalid Content-Length"));
+            }
+            // Too many digits for usize is too large either way
+            first.parse::<usize>().map_err(|_| RequestError::PayloadTooLarge)?
+        }
+    };
+
+    if length > MAX_BODY_BYTES {
+        return Err(RequestError::PayloadTooLarge);
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => RequestError::BadRequest("body shorter than Content-Length"),
+        _ => e.into(),
+    })?;
+    Ok(body)
+}
+
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, RequestError> {
+    let mut body = Vec::new();
+    // Trailers count against the header limit, chunk size lines have a limit of their own
+    let mut trailer_bytes = 0;
+    let truncated = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => RequestError::BadRequest("unexpected end of chunked body"),
+        _ => e.into(),
+    };
+
+    loop {
+        let size_line = read_chunk_line(reader)?;
+        // Ignore chunk extensions ("1a;name=value")
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        if size_hex.is_empty() || !size_hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(RequestError::BadRequest("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| RequestError::PayloadTooLarge)?;
+
+        if size == 0 {
+            break;
+        }
+        // Subtract instead of adding: a huge size must not overflow
+        if size > MAX_BODY_BYTES - body.len() {
+            return Err(RequestError::PayloadTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(truncated)?;
+
+        // Each chunk ends with CRLF
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf).map_err(truncated)?;
+        if &crlf != b"\r\n" {
+            return Err(RequestError::BadRequest("missing CRLF after chunk"));
+        }
+    }
+
+    // Skip trailers until the empty line
+    loop {
+        let trailer = read_head_line(reader, &mut trailer_bytes)?
+            .ok_or(RequestError::BadRequest("unexpected end of chunked body"))?;
+        if trailer.is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+/// Read a chunk size line; malformed framing is a bad request
+fn read_chunk_line<R: BufRead>(reader: &mut R) -> Result<String, RequestError> {
+    let mut line = Vec::new();
+    let read = reader.by_ref().take(MAX_CHUNK_LINE_BYTES as u64).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(RequestError::BadRequest(if read == MAX_CHUNK_LINE_BYTES {
+            "chunk size line too long"
+        } else {
+            "unexpected end of chunked body"
+        }));
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| RequestError::BadRequest("invalid chunk size"))
+}
+
+/// Characters allowed in methods and header names (RFC 9110 token)
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Request, RequestError> {
+        read_request(&mut raw.as_bytes())
+    }
+
+    fn status(raw: &str) -> Option<u16> {
+        parse(raw).err().and_then(|e| e.status()).map(|(code, _)| code)
+    }
+
+    #[test]
+    fn parses_request_with_body() {
+        let request = parse("POST /api/x?a=1 HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\n\r\nabc").unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/x");
+        assert_eq!(request.query.get("a").map(String::as_str), Some("1"));
+        assert_eq!(request.body, b"abc");
+    }
+
+    #[test]
+    fn parses_chunked_body() {
+        let request = parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;x=y\r\nabc\r\n2\r\nde\r\n0\r\n\r\n").unwrap();
+        assert_eq!(request.body, b"abcde");
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert_eq!(status("GET /\r\n\r\n"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\n bad: fold\r\n\r\n"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab"), Some(400));
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n+3\r\nabc\r\n0\r\n\r\n"), Some(400));
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcX\r\n0\r\n\r\n"), Some(400));
+    }
+
+    #[test]
+    fn rejects_broken_chunks() {
+        // Truncated data and missing CRLF are answered, not just dropped
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nab"), Some(400));
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab"), Some(400));
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2"), Some(400));
+        let long_line = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1;{}\r\n", "x".repeat(2000));
+        assert_eq!(status(&long_line), Some(400));
+    }
+
+    #[test]
+    fn parses_many_small_chunks() {
+        // Far more chunk size lines than fit in the header limit
+        let chunks = "1\r\na\r\n".repeat(10_000);
+        let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}0\r\n\r\n", chunks);
+        assert_eq!(parse(&raw).unwrap().body.len(), 10_000);
+    }
+
+    #[test]
+    fn rejects_large_bodies() {
+        let too_long = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_BYTES + 1);
+        assert_eq!(status(&too_long), Some(413));
+        assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n"), Some(413));
+
+        // Sizes near usize::MAX must not overflow the limit check
+        let huge_chunk = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n{:x}\r\n", usize::MAX);
+        assert_eq!(status(&huge_chunk), Some(413));
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nfffffffffffffffffffff\r\n"), Some(413));
+    }
+
+    #[test]
+    fn rejects_large_heads() {
+        let long_header = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_HEADER_BYTES));
+        assert_eq!(status(&long_header), Some(431));
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEADER_BYTES));
+        assert_eq!(status(&long_target), Some(431));
+    }
+
+    #[test]
+    fn rejects_unsupported_framing() {
+        assert_eq!(status("GET / HTTP/2.0\r\n\r\n"), Some(505));
+        assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"), Some(501));
+        assert!(matches!(parse(""), Err(RequestError::Closed)));
+    }
+}
@@ -1,31 +1,229 @@
-use super::html::Html;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use serde::Serialize;
+use super::connection::Connection;
+use log::error;
 
-pub fn create_response(status_code: u16, status_text: &str, content_type: &str, body: &[u8]) -> String {
-    let mut response = format!(
-        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
-        status_code,
-        status_text,
-        content_type,
-        body.len()
-    );
+/// Writes a body of unknown length until it is done or the client goes away
+pub type StreamWriter = Box<dyn FnOnce(&mut dyn Write) -> io::Result<u64> + Send>;
 
-    // Append body as string (works for text content)
-    response.push_str(&String::from_utf8_lossy(body));
+/// Takes over the connection once the response head was sent (WebSocket)
+pub type UpgradeHandler = Box<dyn FnOnce(Connection) + Send>;
 
-    response
+/// Response body: in-memory bytes, a file streamed from disk, a long-lived stream
+/// or a protocol switch
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// File and its length in bytes
+    File(File, u64),
+    /// Ends when the connection is closed (Server-Sent Events)
+    Stream(StreamWriter),
+    /// Sent with 101 Switching Protocols; the server hands the connection to the handler
+    Upgrade(UpgradeHandler),
 }
 
-pub fn create_html_response(status_code: u16, status_text: &str, html: Html) -> String {
-    let mut response = format!(
-        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
-        status_code,
-        status_text,
-        html.content_type,
-        html.content.len()
-    );
+impl Body {
+    /// Length in bytes, None for streams and upgrades
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(_, len) => Some(*len),
+            Body::Stream(_) | Body::Upgrade(_) => None,
+        }
+    }
+}
+
+/// An HTTP response; Content-Length is always derived from the body
+/// (streams have none and close the connection when they end)
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Body,
+    /// Answer to HEAD: only the head is written, with the headers the body would have
+    omit_body: bool,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: Body) -> Response {
+        let mut response = Response {
+            status,
+            headers: Vec::new(),
+            body,
+            omit_body: false,
+        };
+        response.set_header("Content-Type", content_type);
+        response
+    }
+
+    /// Plain text response
+    pub fn text(status: u16, text: &str) -> Response {
+        Response::new(status, "text/plain; charset=utf-8", Body::Bytes(text.as_bytes().to_vec()))
+    }
+
+    /// Plain text response with the status reason as body ("404 Not Found")
+    pub fn error(status: u16) -> Response {
+        Response::text(status, status_text(status))
+    }
+
+    pub fn html(html: String) -> Response {
+        Response::new(200, "text/html; charset=utf-8", Body::Bytes(html.into_bytes()))
+    }
+
+    /// 304 Not Modified, without body
+    pub fn not_modified() -> Response {
+        Response {
+            status: 304,
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+            omit_body: false,
+        }
+    }
+
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        match serde_json::to_vec(value) {
+            Ok(json) => Response::new(status, "application/json", Body::Bytes(json)),
+            Err(e) => {
+                error!("Failed to serialize response: {}", e);
+                Response::error(500)
+            }
+        }
+    }
+
+    /// Response whose body is written by `writer` for as long as the connection lives
+    pub fn stream<F>(content_type: &str, writer: F) -> Response
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<u64> + Send + 'static,
+    {
+        Response::new(200, content_type, Body::Stream(Box::new(writer)))
+    }
 
-    // Append body as string (works for text content)
-    response.push_str(&*html.content);
+    pub fn is_stream(&self) -> bool {
+        matches!(self.body, Body::Stream(_))
+    }
+
+    /// 101 Switching Protocols; `handler` gets the connection after the head was written
+    pub fn upgrade<F>(handler: F) -> Response
+    where
+        F: FnOnce(Connection) + Send + 'static,
+    {
+        Response {
+            status: 101,
+            headers: Vec::new(),
+            body: Body::Upgrade(Box::new(handler)),
+            omit_body: false,
+        }
+    }
+
+    /// Remove the upgrade handler, leaving an empty body (only the head is written then)
+    pub fn take_upgrade(&mut self) -> Option<UpgradeHandler> {
+        match std::mem::replace(&mut self.body, Body::Bytes(Vec::new())) {
+            Body::Upgrade(handler) => Some(handler),
+            body => {
+                self.body = body;
+                None
+            }
+        }
+    }
+
+    /// Send the head only (HEAD requests); Content-Length still describes the body
+    pub fn omit_body(&mut self) {
+        self.omit_body = true;
+    }
+
+    /// Look up a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Add or replace a header
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.set_header(name, value);
+        self
+    }
+
+    /// Add or replace a header; line breaks are dropped so a value can't add headers of its own
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        let value: String = value.chars().filter(|c| !matches!(c, '\r' | '\n' | '\0')).collect();
+        self.headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value));
+    }
+
+    /// Write status line, headers and body; returns the number of body bytes written
+    pub fn write_to<W: Write>(self, out: &mut W) -> io::Result<u64> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, status_text(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match self.body.len() {
+            // 101 has no body (the connection now speaks another protocol),
+            // 304 none of its own (Content-Length would describe the cached body)
+            _ if self.status == 101 || self.status == 304 => head.push_str("\r\n"),
+            Some(len) => head.push_str(&format!("Content-Length: {}\r\n\r\n", len)),
+            None => head.push_str("Connection: close\r\n\r\n"),
+        }
+        out.write_all(head.as_bytes())?;
+        if self.omit_body {
+            out.flush()?;
+            return Ok(0);
+        }
+
+        let written = match self.body {
+            Body::Bytes(bytes) => {
+                out.write_all(&bytes)?;
+                bytes.len() as u64
+            }
+            Body::File(file, len) => {
+                // Stream in chunks; never send more than the announced length
+                let copied = io::copy(&mut file.take(len), out)?;
+                if copied != len {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while sending"));
+                }
+                copied
+            }
+            Body::Stream(writer) => {
+                out.flush()?;
+                writer(out)?
+            }
+            Body::Upgrade(_) => {
+                return Err(io::Error::other("upgrade responses must be taken over by the server"));
+            }
+        };
+
+        out.flush()?;
+        Ok(written)
+    }
+}
 
-    response
+/// Reason phrase for a status code
+pub fn status_text(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        410 => "Gone",
+        413 => "Payload Too Large",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
 }
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use super::request::Request;
+use super::response::Response;
+use super::url::percent_decode;
+
+/// Path parameters captured by a route pattern (`/api/events/:id` -> "id")
+pub type Params = HashMap<String, String>;
+
+type Handler<S> = Box<dyn Fn(&S, &Request, &Params) -> Response + Send + Sync>;
+
+/// Routes requests by method and path pattern to handlers sharing state `S`.
+///
+/// Patterns are matched segment by segment: literals must match exactly,
+/// `:name` captures one segment and a trailing `*` captures the rest of the path.
+pub struct Router<S> {
+    state: Arc<S>,
+    routes: Vec<Route<S>>,
+}
+
+struct Route<S> {
+    method: String,
+    /// Pattern as registered ("/api/events/:id")
+    path: String,
+    pattern: Vec<Segment>,
+    handler: Handler<S>,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest,
+}
+
+impl<S> Router<S> {
+    pub fn new(state: Arc<S>) -> Router<S> {
+        Router {
+            state,
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Router<S>
+    where
+        F: Fn(&S, &Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Router<S>
+    where
+        F: Fn(&S, &Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    pub fn delete<F>(&mut self, pattern: &str, handler: F) -> &mut Router<S>
+    where
+        F: Fn(&S, &Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("DELETE", pattern, handler)
+    }
+
+    /// Register a handler; earlier routes win when several patterns match
+    pub fn route<F>(&mut self, method: &str, pattern: &str, handler: F) -> &mut Router<S>
+    where
+        F: Fn(&S, &Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        let segments = split_path(pattern)
+            .map(|segment| match segment {
+                "*" => Segment::Rest,
+                _ if segment.starts_with(':') => Segment::Param(segment[1..].to_string()),
+                _ => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+
+        self.routes.push(Route {
+            method: method.to_string(),
+            path: pattern.to_string(),
+            pattern: segments,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Dispatch a request: 404 if no pattern matches, 405 (with Allow) if only the method differs.
+    /// HEAD is answered by the GET handler (the server leaves out the body).
+    /// Also returns the pattern of the route that answered ("-" if none), for metrics.
+    pub fn handle(&self, request: &Request) -> (Response, &str) {
+        let segments: Vec<String> = split_path(&request.path).map(percent_decode).collect();
+        let mut allowed: Vec<&str> = Vec::new();
+
+        for route in &self.routes {
+            let params = match match_pattern(&route.pattern, &segments) {
+                Some(params) => params,
+                None => continue,
+            };
+
+            if route.method == request.method || (request.method == "HEAD" && route.method == "GET") {
+                return ((route.handler)(&self.state, request, &params), &route.path);
+            }
+            let methods: &[&str] = if route.method == "GET" { &["GET", "HEAD"] } else { &[route.method.as_str()] };
+            for method in methods {
+                if !allowed.contains(method) {
+                    allowed.push(method);
+                }
+            }
+        }
+
+        let response = if allowed.is_empty() {
+            Response::error(404)
+        } else {
+            Response::error(405).with_header("Allow", &allowed.join(", "))
+        };
+        (response, "-")
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn match_pattern(pattern: &[Segment], segments: &[String]) -> Option<Params> {
+    let mut params = Params::new();
+
+    for (i, part) in pattern.iter().enumerate() {
+        match part {
+            Segment::Rest => {
+                params.insert("*".to_string(), segments.get(i..).unwrap_or(&[]).join("/"));
+                return Some(params);
+            }
+            Segment::Literal(literal) => {
+                if segments.get(i) != Some(literal) {
+                    return None;
+                }
+            }
+            Segment::Param(name) => {
+                params.insert(name.clone(), segments.get(i)?.clone());
+            }
+        }
+    }
+
+    if segments.len() == pattern.len() {
+        Some(params)
+    } else {
+        None
+    }
+}
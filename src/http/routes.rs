@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use super::auth::{require, Scope};
+use super::cache::{policy, NO_STORE, REVALIDATE, STATIC};
+use super::router::Router;
+use super::state::AppState;
+use super::{agenda, api, file_server, kiosk, login, share, sse};
+
+/// All routes of the server; new endpoints are registered here
+/// together with their Cache-Control policy and the scope they require
+pub fn build_router(state: Arc<AppState>) -> Router<AppState> {
+    let mut router = Router::new(state);
+
+    router
+        // Login is open to everyone
+        .get("/login", login::form)
+        .post("/login", login::login)
+        .post("/logout", login::logout)
+        // Probes of supervisors and load balancers don't log in
+        .get("/healthz", policy(NO_STORE, api::healthz))
+        .get("/readyz", policy(NO_STORE, api::readyz))
+        // Share links carry their own token
+        .get("/s/:token", policy(NO_STORE, share::page))
+        .get("/s/:token/events.json", policy(NO_STORE, share::events_json))
+        .get("/s/:token/calendar.ics", policy(NO_STORE, share::ics))
+        .get("/", require(Scope::Read, policy(REVALIDATE, agenda::index)))
+        .get("/api/last-update", require(Scope::Read, policy(NO_STORE, api::last_update)))
+        .get("/api/next", require(Scope::Read, policy(NO_STORE, api::next_event)))
+        .get("/api/stream", require(Scope::Read, policy(NO_STORE, sse::stream)))
+        .get("/api/ws", require(Scope::Read, kiosk::connect))
+        .get("/api/events", require(Scope::Read, policy(REVALIDATE, api::list_events)))
+        .get("/api/events/:id", require(Scope::Read, policy(REVALIDATE, api::get_event)))
+        .get("/metrics", require(Scope::Read, policy(NO_STORE, api::metrics)))
+        .post("/api/refresh", require(Scope::Refresh, policy(NO_STORE, api::refresh)))
+        .get("/api/shares", require(Scope::Admin, policy(NO_STORE, share::list)))
+        .post("/api/shares", require(Scope::Admin, policy(NO_STORE, share::create)))
+        .delete("/api/shares/:id", require(Scope::Admin, share::revoke))
+        // Everything else is looked up in public/ (fingerprinted files are immutable)
+        .get("/*", require(Scope::Read, policy(STATIC, file_server::static_files)));
+
+    router
+}
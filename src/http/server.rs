@@ -1,388 +1,340 @@
-use std::net::{TcpListener, TcpStream};
-use std::io::{Read, Write};
-use super::file_server::serve_file;
-use super::response::{create_html_response, create_response};
-use crate::calendar::{load_events, get_last_update};
-use chrono::{Local, NaiveDateTime, TimeZone};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use super::compression;
+use super::connection::{Connection, Deadline};
+use super::pool::WorkerPool;
+use super::response::Response;
+use super::request::{read_request, Request};
+use super::router::Router;
+use super::routes::build_router;
+use super::state::AppState;
+use super::{redirect, tls};
+use crate::logging::{self, AccessEntry};
+use crate::metrics::Metrics;
+use crate::signals::Shutdown;
+use log::{debug, error, info, warn};
+
+/// How long a single read may wait for the client
+const READ_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+/// How long a client may take to send a whole request, from its first byte (408 after that)
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+/// How long we wait for a client to accept a response
+const WRITE_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+/// Limits for persistent connections
+struct KeepAlive {
+    /// How long to wait for the next request
+    idle_timeout: StdDuration,
+    /// Requests per connection; the last response says `Connection: close`
+    max_requests: usize,
+}
+
+/// Serve until `shutdown` is requested, then finish in-flight requests within the shutdown timeout;
+/// false if some were cut off. Errors if the server can't start (listening, TLS).
+pub fn serve(state: AppState, shutdown: Arc<Shutdown>) -> Result<bool, String> {
+    // Settings read here need a restart to change
+    let config = state.config.get();
+    let addr = &config.server_address;
+
+    // HTTPS when a certificate is configured
+    let tls = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            Some(tls::server_config(cert_path, key_path).map_err(|e| format!("Failed to set up TLS: {}", e))?)
+        }
+        _ => None,
+    };
 
-pub fn serve(addr: &str) {
-    let listener = TcpListener::bind(addr).unwrap();
-    println!("Server running on {addr}");
+    let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    if let Ok(local_addr) = listener.local_addr() {
+        shutdown.set_listener(local_addr);
+    }
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    info!(
+        "Server running on {scheme}://{addr} ({} workers, queue limit {})",
+        config.worker_threads, config.worker_queue_limit
+    );
+
+    if let Some(redirect_address) = &config.http_redirect_address {
+        let https_port = listener.local_addr().map(|addr| addr.port()).unwrap_or(443);
+        redirect::start_redirect_listener(redirect_address, https_port)?;
+    }
+
+    let (worker_threads, worker_queue_limit) = (config.worker_threads, config.worker_queue_limit);
+    let keep_alive = KeepAlive {
+        idle_timeout: StdDuration::from_secs(config.keep_alive_timeout),
+        // A timeout of 0 turns keep-alive off
+        max_requests: if config.keep_alive_timeout == 0 { 1 } else { config.max_requests_per_connection },
+    };
+    let shutdown_timeout = StdDuration::from_secs(config.shutdown_timeout);
+    let metrics = Arc::clone(&state.metrics);
+    let router = Arc::new(build_router(Arc::new(state)));
+
+    // Connections are handled by a bounded pool, so one slow client can't block the others
+    let pool_shutdown = Arc::clone(&shutdown);
+    let pool = WorkerPool::new(worker_threads, worker_queue_limit, move |stream| {
+        handle_connection(stream, &router, &keep_alive, tls.as_ref(), &metrics, &pool_shutdown);
+    });
 
     for stream in listener.incoming() {
+        // The connection that woke us up is dropped along with the listener
+        if shutdown.is_requested() {
+            break;
+        }
         match stream {
             Ok(stream) => {
-                handle_connection(stream);
+                if let Err(stream) = pool.try_submit(stream) {
+                    // A plain 503 would only confuse TLS clients; they just see the connection close
+                    if scheme == "http" {
+                        reject_overloaded(stream);
+                    }
+                }
             }
             Err(e) => {
-                eprintln!("Connection failed: {}", e);
+                warn!("Connection failed: {}", e);
             }
         }
     }
-}
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 1024];
-
-    match stream.read(&mut buffer) {
-        Ok(_) => {
-            let request = String::from_utf8_lossy(&buffer[..]);
-            let request_line = request.lines().next().unwrap_or("");
-
-            println!("Request: {}", request_line);
-
-            let response = process_request(request_line);
-            stream.write_all(response.as_bytes()).unwrap();
-            stream.flush().unwrap();
-        }
-        Err(e) => {
-            eprintln!("Failed to read from connection: {}", e);
-        }
+    drop(listener);
+    info!("Waiting up to {} seconds for in-flight requests", shutdown_timeout.as_secs());
+    let drained = pool.shutdown(Instant::now() + shutdown_timeout);
+    if !drained {
+        warn!("Shutdown timeout reached, cutting off requests still in progress");
     }
+    Ok(drained)
 }
 
-fn process_request(request_line: &str) -> String {
-    let parts: Vec<&str> = request_line.split_whitespace().collect();
-
-    if parts.len() < 2 {
-        return create_response(400, "Bad Request", "text/plain", b"Bad Request");
-    }
-
-    let method = parts[0];
-    let path = parts[1];
-
-    if method != "GET" {
-        return create_response(405, "Method Not Allowed", "text/plain", b"Method Not Allowed");
-    }
-
-    // Serve dynamic index with events
-    if path == "/" {
-        return generate_index_html();
-    }
+/// Answer 503 directly from the accept loop when all workers are busy
+fn reject_overloaded(mut stream: TcpStream) {
+    warn!("Server busy, rejecting connection");
+    let _ = stream.set_write_timeout(Some(StdDuration::from_secs(1)));
+    let _ = Response::error(503)
+        .with_header("Retry-After", "1")
+        .with_header("Connection", "close")
+        .write_to(&mut stream);
+}
 
-    // API endpoint for last update timestamp
-    if path == "/api/last-update" {
-        let timestamp = get_last_update();
-        let json = format!(r#"{{"last_update":"{}"}}"#, timestamp);
-        return format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-            json.len(),
-            json
-        );
+fn handle_connection(
+    stream: TcpStream,
+    router: &Router<AppState>,
+    keep_alive: &KeepAlive,
+    tls: Option<&Arc<ServerConfig>>,
+    metrics: &Metrics,
+    shutdown: &Shutdown,
+) {
+    // Streams and WebSockets take it along to their own thread
+    let active = metrics.connection();
+
+    if let Err(e) = stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+    {
+        warn!("Failed to configure connection: {}", e);
+        return;
     }
 
-    // Serve static files
-    let result = serve_file(path);
-
-    match result {
-        Ok(html) => {
-            create_html_response(200, "Ok", html)
-        }
-        Err(e) => {
-            eprintln!("{}", e);
-            create_response(404, "Not found", "text/plain", b"Not Found")
-        }
-    }
-}
-
-fn generate_index_html() -> String {
-    let events = load_events();
-    let now = Local::now();
-
-    // Filter events: only show current or future events (end_date >= now)
-    let future_events: Vec<_> = events
-        .iter()
-        .filter(|event| {
-            // Parse end_date (format: YYYY-MM-DD HH:MM:SS)
-            if let Ok(end_dt) = NaiveDateTime::parse_from_str(&event.end_date, "%Y-%m-%d %H:%M:%S") {
-                // Convert to Local timezone for comparison
-                let end_local = Local::now().timezone().from_local_datetime(&end_dt).single();
-                if let Some(end_time) = end_local {
-                    return end_time >= now;
+    // The TLS handshake happens on the first read
+    let connection = match tls {
+        Some(tls) => match ServerConnection::new(Arc::clone(tls)) {
+            Ok(session) => Connection::Tls(Box::new(StreamOwned::new(session, stream))),
+            Err(e) => {
+                error!("Failed to start TLS session: {}", e);
+                return;
+            }
+        },
+        None => Connection::Plain(stream),
+    };
+
+    // One reader for the whole connection, so pipelined requests already buffered are kept;
+    // responses are written to the connection underneath it
+    let remote_addr = connection.tcp().peer_addr().ok().map(|addr| addr.ip());
+    let mut reader = BufReader::new(connection);
+
+    for served in 1..=keep_alive.max_requests {
+        if served > 1 && !wait_for_request(&mut reader, keep_alive.idle_timeout) {
+            return;
+        }
+
+        let result = read_request(&mut Deadline::new(&mut reader, READ_TIMEOUT, REQUEST_TIMEOUT));
+        let started = Instant::now();
+        // Method and route for metrics; rejected requests have neither
+        let (mut response, persistent, method, route, request_info) = match result {
+            Ok(mut request) => {
+                request.remote_addr = remote_addr;
+                // During a shutdown every connection closes after its current request
+                let persistent =
+                    wants_keep_alive(&request) && served < keep_alive.max_requests && !shutdown.is_requested();
+                let (mut response, route) = process_request(&request, router);
+                if persistent && request.version == "HTTP/1.0" {
+                    response.set_header("Connection", "keep-alive");
+                    let remaining = keep_alive.max_requests - served;
+                    response.set_header(
+                        "Keep-Alive",
+                        &format!("timeout={}, max={}", keep_alive.idle_timeout.as_secs(), remaining),
+                    );
                 }
+                let request_info = RequestInfo::of(&request);
+                (response, persistent, request.method, route, Some(request_info))
             }
-            false // If parsing fails, exclude the event
-        })
-        .collect();
-
-    let events_to_show: Vec<_> = future_events.iter().take(10).collect();
-
-    let mut html = String::from(r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>MY AGENDA</title>
-    <style>
-        * {
-            margin: 0;
-            padding: 0;
-            box-sizing: border-box;
-        }
-        body {
-            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
-            background-color: #000;
-            color: #fff;
-            padding: 40px 20px;
-            max-width: 700px;
-            margin: 0 auto;
-        }
-        .header {
-            text-align: right;
-            font-size: 16px;
-            letter-spacing: 2px;
-            color: #999;
-            margin-bottom: 40px;
-        }
-        .day-section {
-            margin-bottom: 30px;
-            border-bottom: 1px solid #333;
-            padding-bottom: 20px;
-        }
-        .day-header {
-            font-size: 22px;
-            font-weight: bold;
-            text-transform: uppercase;
-            margin-bottom: 15px;
-            letter-spacing: 1px;
-        }
-        .event-item {
-            display: flex;
-            align-items: flex-start;
-            margin-bottom: 15px;
-            padding-left: 0;
-        }
-        .event-dot {
-            width: 14px;
-            height: 14px;
-            border-radius: 50%;
-            margin-right: 12px;
-            margin-top: 5px;
-            flex-shrink: 0;
-        }
-        .event-dot.active {
-            background-color: #ff6b35;
-        }
-        .event-dot.past {
-            background-color: #666;
-        }
-        .event-dot.video {
-            background-color: #4a9eff;
-        }
-        .event-dot.cal0 {
-            background-color: #ff6b35;
-        }
-        .event-dot.cal1 {
-            background-color: #4a9eff;
-        }
-        .event-dot.cal2 {
-            background-color: #9b59b6;
-        }
-        .event-dot.cal3 {
-            background-color: #2ecc71;
-        }
-        .event-dot.cal4 {
-            background-color: #f1c40f;
-        }
-        .event-dot.cal5 {
-            background-color: #e74c3c;
-        }
-        .event-content {
-            flex: 1;
-        }
-        .event-time {
-            color: #ccc;
-            font-size: 26px;
-            margin-right: 10px;
-        }
-        .event-title {
-            color: #fff;
-            font-size: 28px;
-            display: inline;
-            font-weight: 500;
-        }
-        .event-tag {
-            display: inline-block;
-            background-color: #4a4a4a;
-            color: #fff;
-            padding: 2px 10px;
-            border-radius: 4px;
-            font-size: 12px;
-            margin-right: 6px;
-            margin-bottom: 4px;
-        }
-        .event-tag.green {
-            background-color: #2d5f2e;
-        }
-        .event-tag.blue {
-            background-color: #1e3a5f;
-        }
-        .event-tag.orange {
-            background-color: #5f3a1e;
-        }
-        .event-status {
-            color: #999;
-            font-size: 12px;
-            margin-left: 8px;
-        }
-        .event-location {
-            color: #999;
-            font-size: 14px;
-            text-align: right;
-            margin-top: -20px;
-            margin-bottom: 10px;
-        }
-        .event-description {
-            color: #999;
-            font-size: 14px;
-            margin-left: 26px;
-            margin-top: 6px;
-        }
-        .no-events {
-            text-align: center;
-            padding: 60px 20px;
-            color: #666;
-            font-size: 14px;
-        }
-    </style>
-</head>
-<body>
-    <div class="header">MY AGENDA</div>
-"#);
-
-    if events_to_show.is_empty() {
-        html.push_str(r#"<div class="no-events">No events found. Check back later!</div>"#);
-    } else {
-        let mut last_date = String::new();
-
-        for event in events_to_show {
-            // Extract date from start_date (assuming format: YYYY-MM-DD HH:MM:SS)
-            let date_part = event.start_date.split_whitespace().next().unwrap_or("");
-
-            // If new day, create day section
-            if date_part != last_date {
-                if !last_date.is_empty() {
-                    html.push_str("    </div>\n");
+            // After a malformed request we can't tell where the next one starts
+            Err(e) => match e.status() {
+                Some((status_code, _)) => {
+                    info!("Rejected request: {}", e);
+                    (Response::error(status_code), false, "other".to_string(), "-", None)
                 }
-
-                html.push_str(&format!(r#"    <div class="day-section">
-        <div class="day-header">{}</div>
-"#, format_date(date_part)));
-
-                last_date = date_part.to_string();
+                None => {
+                    if served == 1 {
+                        debug!("Failed to read from connection: {}", e);
+                    }
+                    return;
+                }
+            },
+        };
+
+        let status = response.status;
+        let log_access = |bytes: u64| log_access(remote_addr, request_info.as_ref(), status, bytes, started.elapsed());
+
+        // Protocol switch (WebSocket): send the 101 head, then the handler owns the connection
+        if let Some(upgrade) = response.take_upgrade() {
+            let mut connection = reader.into_inner();
+            let written = response.write_to(&mut connection);
+            metrics.record_request(&method, route, status, started.elapsed());
+            match written {
+                Ok(bytes) => {
+                    log_access(bytes);
+                    thread::spawn(move || {
+                        let _active = active;
+                        upgrade(connection)
+                    });
+                }
+                Err(e) => debug!("Failed to write response: {}", e),
             }
+            return;
+        }
+
+        // Event streams run until the client leaves; give them their own thread instead of a worker.
+        // Their latency is the time until the stream started; they are logged then, without a size.
+        if response.is_stream() {
+            metrics.record_request(&method, route, status, started.elapsed());
+            log_access(0);
+            let mut connection = reader.into_inner();
+            thread::spawn(move || {
+                let _active = active;
+                if let Err(e) = response.write_to(&mut connection) {
+                    debug!("Event stream closed: {}", e);
+                }
+            });
+            return;
+        }
 
-            // Extract time from start and end
-            let start_time = extract_time(&event.start_date);
-            let end_time = extract_time(&event.end_date);
-
-            // Use calendar_id for color (fallback to "active" if empty)
-            let dot_class = if !event.calendar_id.is_empty() {
-                &event.calendar_id
-            } else {
-                "active"
-            };
-
-            html.push_str(&format!(r#"        <div class="event-item">
-            <div class="event-dot {}"></div>
-            <div class="event-content">
-                <span class="event-time">{} - {}</span>
-                <span class="event-title">{}</span>
-"#,
-                dot_class,
-                escape_html(&start_time),
-                escape_html(&end_time),
-                escape_html(&event.title)
-            ));
-
-            // Add location if present
-            if !event.location.is_empty() {
-                html.push_str(&format!(r#"
-                <div class="event-location">{}</div>
-"#, escape_html(&event.location)));
+        if !persistent {
+            response.set_header("Connection", "close");
+        }
+        let written = response.write_to(reader.get_mut());
+        metrics.record_request(&method, route, status, started.elapsed());
+        match written {
+            Ok(bytes) => log_access(bytes),
+            Err(e) => {
+                debug!("Failed to write response: {}", e);
+                return;
             }
+        }
+        if !persistent {
+            return;
+        }
+    }
+}
 
-            // Add description if present
-            if !event.description.is_empty() {
-                html.push_str(&format!(r#"
-                <div class="event-description">{}</div>
-"#, escape_html(&event.description)));
-            }
+/// What the access log needs of a request, kept after the request is gone
+struct RequestInfo {
+    request_line: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
 
-            html.push_str("            </div>\n        </div>\n");
+impl RequestInfo {
+    fn of(request: &Request) -> RequestInfo {
+        RequestInfo {
+            request_line: format!(
+                "{} {} {}",
+                request.method,
+                logging::loggable_target(&request.path, &request.query_string),
+                request.version
+            ),
+            referer: request.header("referer").map(|value| value.to_string()),
+            user_agent: request.header("user-agent").map(|value| value.to_string()),
         }
+    }
+}
 
-        if !last_date.is_empty() {
-            html.push_str("    </div>\n");
-        }
+fn log_access(
+    remote_addr: Option<IpAddr>,
+    request: Option<&RequestInfo>,
+    status: u16,
+    bytes: u64,
+    duration: StdDuration,
+) {
+    logging::access(&AccessEntry {
+        remote_addr,
+        request_line: request.map(|request| request.request_line.clone()),
+        status,
+        bytes,
+        referer: request.and_then(|request| request.referer.as_deref()),
+        user_agent: request.and_then(|request| request.user_agent.as_deref()),
+        duration,
+    });
+}
+
+/// Wait for the next request on a kept-alive connection;
+/// false if the client closed it or stayed idle for `idle_timeout`
+fn wait_for_request(reader: &mut BufReader<Connection>, idle_timeout: StdDuration) -> bool {
+    // Pipelined requests are already waiting in the buffer
+    if !reader.buffer().is_empty() {
+        return true;
     }
 
-    // Add JavaScript for auto-reload on updates
-    html.push_str(r#"
-    <script>
-        // Get initial timestamp
-        let lastKnownUpdate = null;
-
-        // Fetch current timestamp on load
-        fetch('/api/last-update')
-            .then(r => r.json())
-            .then(data => {
-                lastKnownUpdate = data.last_update;
-            });
+    if reader.get_ref().set_read_timeout(Some(idle_timeout)).is_err() {
+        return false;
+    }
+    let ready = matches!(reader.fill_buf(), Ok(buffer) if !buffer.is_empty());
 
-        // Check for updates every minute
-        setInterval(() => {
-            fetch('/api/last-update')
-                .then(r => r.json())
-                .then(data => {
-                    if (lastKnownUpdate && data.last_update > lastKnownUpdate) {
-                        console.log('Events updated, reloading...');
-                        location.reload();
-                    }
-                    lastKnownUpdate = data.last_update;
-                })
-                .catch(err => console.error('Failed to check for updates:', err));
-        }, 60000); // Check every 60 seconds
-    </script>
-"#);
-
-    html.push_str("</body></html>");
-
-    // Build HTTP response
-    format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-        html.len(),
-        html
-    )
+    // Once the request started, the usual limit for sending it applies
+    ready && reader.get_ref().set_read_timeout(Some(READ_TIMEOUT)).is_ok()
 }
 
-fn format_date(date_str: &str) -> String {
-    // Input format: YYYY-MM-DD
-    // Output format: DD. MONTH
-    let parts: Vec<&str> = date_str.split('-').collect();
-    if parts.len() == 3 {
-        let months = ["", "JANUARY", "FEBRUARY", "MARCH", "APRIL", "MAY", "JUNE",
-                     "JULY", "AUGUST", "SEPTEMBER", "OCTOBER", "NOVEMBER", "DECEMBER"];
-        let month_idx: usize = parts[1].parse().unwrap_or(1);
-        let day = parts[2].parse::<u32>().unwrap_or(1);
-        format!("{}. {}", day, months.get(month_idx).unwrap_or(&"UNKNOWN"))
-    } else {
-        date_str.to_string()
+/// HTTP/1.1 connections persist unless the client sends `Connection: close`;
+/// HTTP/1.0 only with `Connection: keep-alive`
+fn wants_keep_alive(request: &Request) -> bool {
+    let has_token = |token: &str| {
+        request
+            .header("connection")
+            .map(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    };
+
+    match request.version.as_str() {
+        "HTTP/1.1" => !has_token("close"),
+        _ => has_token("keep-alive"),
     }
 }
 
-fn extract_time(datetime: &str) -> String {
-    // Extract HH:MM from "YYYY-MM-DD HH:MM:SS"
-    datetime.split_whitespace()
-        .nth(1)
-        .and_then(|time| time.split(':').take(2).collect::<Vec<_>>().join(":").into())
-        .unwrap_or_else(|| "00:00".to_string())
-}
+/// Response and the route that produced it
+fn process_request<'r>(request: &Request, router: &'r Router<AppState>) -> (Response, &'r str) {
+    // HTTP/1.1 requires a Host header
+    if request.version == "HTTP/1.1" && request.header("host").is_none() {
+        return (Response::text(400, "Missing Host header"), "-");
+    }
 
-fn escape_html(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\n', "<br>")
-}
\ No newline at end of file
+    let (response, route) = router.handle(request);
+    let mut response = compression::compress(request, response);
+    if request.method == "HEAD" {
+        response.omit_body();
+    }
+    (response, route)
+}
@@ -0,0 +1,170 @@
+use serde::Serialize;
+use super::agenda::render_page;
+use super::api::{api_error, event_list};
+use super::request::Request;
+use super::response::{Body, Response};
+use super::router::Params;
+use super::state::AppState;
+use crate::calendar::share::{self, Mask, NewShare, Share};
+use crate::calendar::{ics_writer, resolve_calendars, Snapshot};
+use crate::calendar::event::Event;
+use crate::calendar::info::CalendarInfo;
+use crate::config::Config;
+use chrono::Utc;
+use log::{error, info};
+
+/// A share as listed by the admin API (without the token hash)
+#[derive(Serialize)]
+struct ShareSummary<'a> {
+    id: &'a str,
+    name: &'a str,
+    calendars: &'a [String],
+    mask: Mask,
+    expires: Option<&'a str>,
+    created: &'a str,
+    expired: bool,
+}
+
+impl<'a> From<&'a Share> for ShareSummary<'a> {
+    fn from(share: &'a Share) -> Self {
+        ShareSummary {
+            id: &share.id,
+            name: &share.name,
+            calendars: &share.calendars,
+            mask: share.mask,
+            expires: share.expires.as_deref(),
+            created: &share.created,
+            expired: share.is_expired(),
+        }
+    }
+}
+
+/// GET /s/:token - the agenda of a share link
+pub fn page(state: &AppState, request: &Request, params: &Params) -> Response {
+    let (share, token) = match resolve(params) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let config = &state.config.get();
+    let snapshot = state.events.snapshot();
+    let calendars = shared_calendars(config, &snapshot, &share);
+    let tag = request.query.get("tag").map(|tag| tag.trim()).filter(|tag| !tag.is_empty());
+
+    let events = share.apply(snapshot.upcoming(Utc::now()), &config.my_emails);
+    let events: Vec<&Event> = events.iter().collect();
+    let html = render_page(config, &events, &calendars, tag, &format!("/s/{}", token), None, None);
+    private(Response::html(html))
+}
+
+/// GET /s/:token/events.json - the events of a share link (query parameters as for /api/events)
+pub fn events_json(state: &AppState, request: &Request, params: &Params) -> Response {
+    let (share, _) = match resolve(params) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    private(event_list(&state.config.get(), request, &state.events.snapshot(), Some(&share)))
+}
+
+/// GET /s/:token/calendar.ics - the events of a share link for calendar apps
+pub fn ics(state: &AppState, _request: &Request, params: &Params) -> Response {
+    let (share, _) = match resolve(params) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    let config = &state.config.get();
+    // The feed has past events too, so this is the one place that copies every event
+    let events = share.apply(state.events.snapshot().events(), &config.my_emails);
+    let ics = ics_writer::write_ics(&events, &share.name, config.tz());
+
+    private(Response::new(200, "text/calendar; charset=utf-8", Body::Bytes(ics.into_bytes())))
+        .with_header("Content-Disposition", "inline; filename=\"calendar.ics\"")
+}
+
+/// GET /api/shares - all shares (admin)
+pub fn list(_state: &AppState, _request: &Request, _params: &Params) -> Response {
+    let shares = match share::load_shares() {
+        Ok(shares) => shares,
+        Err(e) => {
+            error!("Failed to load shares: {}", e);
+            return api_error(500, "failed to load shares");
+        }
+    };
+    let summaries: Vec<ShareSummary> = shares.iter().map(ShareSummary::from).collect();
+    Response::json(200, &serde_json::json!({ "shares": summaries }))
+}
+
+/// POST /api/shares - create a share from `{"name", "calendars", "mask", "expires_in_days"}` (admin).
+/// The token is only returned here.
+pub fn create(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let new: NewShare = match serde_json::from_slice(&request.body) {
+        Ok(new) => new,
+        Err(e) => return api_error(400, &format!("invalid share: {}", e)),
+    };
+    if let Err(e) = new.validate(&state.config.get(), state.events.snapshot().calendars()) {
+        return api_error(400, &e);
+    }
+
+    match share::create_share(new) {
+        Ok((share, token)) => {
+            info!("Created share {} ({})", share.id, share.name);
+            Response::json(
+                201,
+                &serde_json::json!({
+                    "share": ShareSummary::from(&share),
+                    "token": token,
+                    "path": format!("/s/{}", token),
+                }),
+            )
+        }
+        Err(e) => {
+            error!("Failed to save share: {}", e);
+            api_error(500, "failed to save share")
+        }
+    }
+}
+
+/// DELETE /api/shares/:id - revoke a share (admin)
+pub fn revoke(_state: &AppState, _request: &Request, params: &Params) -> Response {
+    let id = params.get("id").map(|id| id.as_str()).unwrap_or("");
+    match share::revoke_share(id) {
+        Ok(true) => {
+            info!("Revoked share {}", id);
+            Response::new(204, "text/plain; charset=utf-8", Body::Bytes(Vec::new()))
+        }
+        Ok(false) => api_error(404, "share not found"),
+        Err(e) => {
+            error!("Failed to save shares: {}", e);
+            api_error(500, "failed to save shares")
+        }
+    }
+}
+
+/// The share of the `token` parameter; 404 for unknown tokens, 410 once expired,
+/// 500 while the shares can't be read (no link works then, not even revoked ones)
+fn resolve(params: &Params) -> Result<(Share, String), Response> {
+    let token = params.get("token").cloned().unwrap_or_default();
+    match share::find_share(&token) {
+        Ok(Some(share)) if share.is_expired() => Err(Response::error(410)),
+        Ok(Some(share)) => Ok((share, token)),
+        Ok(None) => Err(Response::error(404)),
+        Err(e) => {
+            error!("Failed to load shares: {}", e);
+            Err(Response::error(500))
+        }
+    }
+}
+
+/// Calendars visible through a share
+fn shared_calendars(config: &Config, snapshot: &Snapshot, share: &Share) -> Vec<CalendarInfo> {
+    resolve_calendars(config, snapshot.calendars())
+        .into_iter()
+        .filter(|calendar| share.includes_calendar(&calendar.id))
+        .collect()
+}
+
+/// The token is in the URL: keep it out of Referer headers and search engines
+fn private(response: Response) -> Response {
+    response
+        .with_header("Referrer-Policy", "no-referrer")
+        .with_header("X-Robots-Tag", "noindex")
+}
@@ -0,0 +1,105 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use super::request::Request;
+use super::response::Response;
+use super::router::Params;
+use super::state::AppState;
+use crate::calendar::{EventStore, UpdateNotifier};
+use log::warn;
+
+/// A comment is sent this often when nothing changed, so idle connections are not dropped
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Each open stream (or WebSocket) keeps a thread; more clients are asked to retry later
+const MAX_STREAMS: usize = 32;
+
+/// How long browsers wait before reconnecting (milliseconds)
+const RETRY_MS: u32 = 5000;
+
+static OPEN_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// GET /api/stream - Server-Sent Events, an `update` event whenever a sync changed the events
+/// or a kiosk command changed the display.
+///
+/// Clients that send an outdated `Last-Event-ID` (or `?last_event_id=`) get the current state right away.
+pub fn stream(state: &AppState, request: &Request, _params: &Params) -> Response {
+    let slot = match StreamSlot::acquire() {
+        Some(slot) => slot,
+        None => {
+            warn!("Too many event streams, rejecting");
+            return Response::error(503).with_header("Retry-After", "5");
+        }
+    };
+
+    // Reconnecting browsers send Last-Event-ID; the page passes the id it was rendered with
+    let last_event_id = request
+        .header("last-event-id")
+        .or(request.query.get("last_event_id").map(|id| id.as_str()))
+        .map(|id| id.to_string());
+    let updates = Arc::clone(&state.updates);
+    let events = Arc::clone(&state.events);
+
+    Response::stream("text/event-stream", move |out| {
+        let _slot = slot;
+        write_events(&updates, &events, last_event_id, out)
+    })
+}
+
+/// Send events until the client disconnects (which ends the loop with a write error)
+fn write_events(
+    updates: &UpdateNotifier,
+    events: &EventStore,
+    last_event_id: Option<String>,
+    out: &mut dyn Write,
+) -> io::Result<u64> {
+    let mut written = 0;
+    let mut send = |out: &mut dyn Write, message: &str| -> io::Result<()> {
+        out.write_all(message.as_bytes())?;
+        out.flush()?;
+        written += message.len() as u64;
+        Ok(())
+    };
+
+    send(out, &format!("retry: {}\n\n", RETRY_MS))?;
+
+    let mut known = updates.version();
+    if last_event_id.as_deref() != Some(updates.event_id(known).as_str()) {
+        send(out, &update_message(updates, events, known))?;
+    }
+
+    loop {
+        let version = updates.wait_for_change(known, HEARTBEAT_INTERVAL);
+        if version == known {
+            send(out, ": heartbeat\n\n")?;
+        } else {
+            known = version;
+            send(out, &update_message(updates, events, known))?;
+        }
+    }
+}
+
+/// "id: ...\nevent: update\ndata: {...}\n\n"
+fn update_message(updates: &UpdateNotifier, events: &EventStore, version: u64) -> String {
+    let data = serde_json::json!({ "last_update": events.last_update() });
+    format!("id: {}\nevent: update\ndata: {}\n\n", updates.event_id(version), data)
+}
+
+/// Counts towards MAX_STREAMS while a long-lived connection is open
+pub struct StreamSlot;
+
+impl StreamSlot {
+    pub fn acquire() -> Option<StreamSlot> {
+        OPEN_STREAMS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| (open < MAX_STREAMS).then_some(open + 1))
+            .ok()
+            .map(|_| StreamSlot)
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        OPEN_STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use super::auth::Auth;
+use super::kiosk::Display;
+use super::login::LoginThrottle;
+use crate::calendar::{EventStore, UpdateNotifier};
+use crate::config::Config;
+use crate::health::Health;
+use crate::metrics::Metrics;
+use crate::shared::Shared;
+
+/// State shared by all request handlers
+pub struct AppState {
+    /// Replaced when the configuration is reloaded (SIGHUP)
+    pub config: Arc<Shared<Config>>,
+    /// Events and calendars of the last sync, kept in memory
+    pub events: Arc<EventStore>,
+    /// Signalled by the background sync when events changed
+    pub updates: Arc<UpdateNotifier>,
+    /// What kiosk screens show (changed over the WebSocket)
+    pub display: Arc<Display>,
+    /// Users, tokens and sessions (reloaded with the configuration)
+    pub auth: Arc<Shared<Auth>>,
+    /// Failed logins per client, to slow down password guessing
+    pub logins: Arc<LoginThrottle>,
+    /// Notified to make the background sync run now
+    pub refresh: Arc<UpdateNotifier>,
+    /// Request and sync counters for /metrics
+    pub metrics: Arc<Metrics>,
+    /// Cache and feed status for /healthz and /readyz
+    pub health: Arc<Health>,
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use rustls::crypto::ring;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use log::{info, warn};
+
+/// How often the certificate files are checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// TLS settings for the server, using the PEM certificate chain and private key at the given paths
+pub fn server_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>, String> {
+    let resolver = ReloadingResolver::new(cert_path, key_path)?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+/// Serves the certificate from disk and reloads it when the files change (certbot renewals).
+/// A broken new certificate is logged and the previous one kept.
+#[derive(Debug)]
+struct ReloadingResolver {
+    cert_path: String,
+    key_path: String,
+    current: Mutex<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    /// Modification times of certificate and key when they were loaded
+    modified: (Option<SystemTime>, Option<SystemTime>),
+    checked: Instant,
+}
+
+impl ReloadingResolver {
+    fn new(cert_path: &str, key_path: &str) -> Result<ReloadingResolver, String> {
+        let modified = modification_times(cert_path, key_path);
+        let key = load_certified_key(cert_path, key_path)?;
+
+        Ok(ReloadingResolver {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            current: Mutex::new(Loaded {
+                key,
+                modified,
+                checked: Instant::now(),
+            }),
+        })
+    }
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+
+        if current.checked.elapsed() >= RELOAD_CHECK_INTERVAL {
+            current.checked = Instant::now();
+            let modified = modification_times(&self.cert_path, &self.key_path);
+            if modified != current.modified {
+                match load_certified_key(&self.cert_path, &self.key_path) {
+                    Ok(key) => {
+                        info!("Reloaded TLS certificate from {}", self.cert_path);
+                        current.key = key;
+                        current.modified = modified;
+                    }
+                    // Certbot writes both files; try again on the next check
+                    Err(e) => warn!("Failed to reload TLS certificate, keeping the old one: {}", e),
+                }
+            }
+        }
+
+        Some(Arc::clone(&current.key))
+    }
+}
+
+fn modification_times(cert_path: &str, key_path: &str) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+/// Read a PEM certificate chain and private key (PKCS#8, PKCS#1 or SEC1)
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<Arc<CertifiedKey>, String> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate in {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", cert_path));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|e| format!("Invalid private key in {}: {}", key_path, e))?
+        .ok_or_else(|| format!("No private key found in {}", key_path))?;
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|e| format!("Unsupported private key in {}: {}", key_path, e))?;
+
+    Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+}
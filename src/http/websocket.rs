@@ -0,0 +1,362 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use super::connection::Connection;
+use super::request::Request;
+use super::response::Response;
+
+/// Appended to the client's key before hashing (RFC 6455, section 1.3)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message (after joining fragments) we accept
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// How long the rest of a frame may take once its first byte arrived
+const FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Close status codes (RFC 6455, section 7.4.1)
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+pub const CLOSE_INVALID_PAYLOAD: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// Answer a WebSocket handshake request: 101 handing the connection to `handler`,
+/// 400/426 if the request is not a valid RFC 6455 upgrade, or 403 for another origin.
+pub fn accept<F>(request: &Request, handler: F) -> Response
+where
+    F: FnOnce(WebSocket) + Send + 'static,
+{
+    let has_token = |header: &str, token: &str| {
+        request
+            .header(header)
+            .map(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    };
+
+    // A HEAD routed here as GET can't switch protocols
+    if request.method != "GET"
+        || request.version != "HTTP/1.1"
+        || !has_token("upgrade", "websocket")
+        || !has_token("connection", "upgrade")
+    {
+        return Response::text(400, "Expected a WebSocket upgrade");
+    }
+    if request.header("sec-websocket-version") != Some("13") {
+        return Response::error(426).with_header("Sec-WebSocket-Version", "13");
+    }
+
+    // Browsers send cookies on cross-site upgrades, so only pages served by
+    // this host may open one
+    if let Some(origin) = request.header("origin") {
+        if !same_origin(origin, request.header("host").unwrap_or("")) {
+            return Response::text(403, "Cross-origin WebSocket upgrades are not allowed");
+        }
+    }
+
+    // The key must be 16 random bytes, base64-encoded
+    let key = request.header("sec-websocket-key").unwrap_or("").trim();
+    if BASE64.decode(key).map(|bytes| bytes.len() != 16).unwrap_or(true) {
+        return Response::text(400, "Invalid Sec-WebSocket-Key");
+    }
+
+    Response::upgrade(move |connection| handler(WebSocket::new(connection)))
+    .with_header("Upgrade", "websocket")
+    .with_header("Connection", "Upgrade")
+    .with_header("Sec-WebSocket-Accept", &accept_key(key))
+}
+
+/// Whether an Origin header names the same host and port as the Host header
+fn same_origin(origin: &str, host: &str) -> bool {
+    let (scheme, authority) = match origin.trim().split_once("://") {
+        Some(parts) => parts,
+        // "null" (sandboxed pages, file: URLs) never matches
+        None => return false,
+    };
+    let default_port = match scheme.to_ascii_lowercase().as_str() {
+        "http" => ":80",
+        "https" => ":443",
+        _ => return false,
+    };
+    let normalize = |authority: &str| {
+        let authority = authority.trim().to_ascii_lowercase();
+        match authority.strip_suffix(default_port) {
+            Some(stripped) => stripped.to_string(),
+            None => authority,
+        }
+    };
+    !host.is_empty() && normalize(authority) == normalize(host)
+}
+
+/// Sec-WebSocket-Accept for a client key: base64(sha1(key + GUID))
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    BASE64.encode(hasher.finalize())
+}
+
+/// A complete data message (fragments already joined)
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    /// The peer closed the connection (with a close frame or by hanging up)
+    Closed,
+    /// The peer broke the protocol; the connection was closed with this code
+    Protocol(u16, &'static str),
+}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebSocketError::Io(e) => write!(f, "{}", e),
+            WebSocketError::Closed => write!(f, "connection closed"),
+            WebSocketError::Protocol(code, reason) => write!(f, "{} ({})", reason, code),
+        }
+    }
+}
+
+impl From<io::Error> for WebSocketError {
+    fn from(e: io::Error) -> WebSocketError {
+        WebSocketError::Io(e)
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Server side of a WebSocket connection
+pub struct WebSocket {
+    /// Frames are read through the buffer and written to the connection directly
+    reader: BufReader<Connection>,
+    closed: bool,
+}
+
+impl WebSocket {
+    fn new(connection: Connection) -> WebSocket {
+        WebSocket {
+            reader: BufReader::new(connection),
+            closed: false,
+        }
+    }
+
+    /// Wait up to `timeout` for incoming data; false if nothing arrived
+    pub fn wait_readable(&mut self, timeout: Duration) -> Result<bool, WebSocketError> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+
+        // Nothing is consumed here, so a timeout never splits a frame
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        match self.reader.fill_buf() {
+            Ok([]) => Err(WebSocketError::Closed),
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read the next data message. Pings are answered and pongs skipped on the way;
+    /// a close frame is answered and ends the connection with `Closed`.
+    pub fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        self.reader.get_ref().set_read_timeout(Some(FRAME_TIMEOUT))?;
+        // Opcode and payload of a fragmented message in progress
+        let mut partial: Option<(u8, Vec<u8>)> = None;
+
+        loop {
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(WebSocketError::Protocol(code, reason)) => {
+                    let _ = self.close(code, reason);
+                    return Err(WebSocketError::Protocol(code, reason));
+                }
+                Err(e) => return Err(e),
+            };
+
+            match frame.opcode {
+                OP_PING => self.send_frame(OP_PONG, &frame.payload)?,
+                OP_PONG => {}
+                OP_CLOSE => {
+                    return match close_code(&frame.payload) {
+                        Ok(code) => {
+                            let _ = self.close(code.unwrap_or(CLOSE_NORMAL), "");
+                            Err(WebSocketError::Closed)
+                        }
+                        Err(reason) => Err(self.fail(CLOSE_PROTOCOL_ERROR, reason)),
+                    };
+                }
+                OP_TEXT | OP_BINARY if partial.is_some() => {
+                    return Err(self.fail(CLOSE_PROTOCOL_ERROR, "new message inside a fragmented message"));
+                }
+                OP_CONTINUATION if partial.is_none() => {
+                    return Err(self.fail(CLOSE_PROTOCOL_ERROR, "continuation without a message"));
+                }
+                opcode => {
+                    let (opcode, payload) = match partial.take() {
+                        Some((first, mut payload)) => {
+                            payload.extend_from_slice(&frame.payload);
+                            (first, payload)
+                        }
+                        None => (opcode, frame.payload),
+                    };
+                    if payload.len() > MAX_MESSAGE_BYTES {
+                        return Err(self.fail(CLOSE_TOO_BIG, "message too big"));
+                    }
+                    if !frame.fin {
+                        partial = Some((opcode, payload));
+                        continue;
+                    }
+
+                    if opcode == OP_BINARY {
+                        return Ok(Message::Binary(payload));
+                    }
+                    return match String::from_utf8(payload) {
+                        Ok(text) => Ok(Message::Text(text)),
+                        Err(_) => Err(self.fail(CLOSE_INVALID_PAYLOAD, "text is not valid UTF-8")),
+                    };
+                }
+            }
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.send_frame(OP_TEXT, text.as_bytes())
+    }
+
+    pub fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        self.send_frame(OP_PING, b"")
+    }
+
+    /// Send a close frame (once); the caller drops the connection afterwards
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+
+        let mut payload = code.to_be_bytes().to_vec();
+        // Control frame payloads are limited to 125 bytes
+        payload.extend(reason.bytes().take(123));
+        self.send_frame(OP_CLOSE, &payload)
+    }
+
+    /// Close with `code` and return the matching error
+    fn fail(&mut self, code: u16, reason: &'static str) -> WebSocketError {
+        let _ = self.close(code, reason);
+        WebSocketError::Protocol(code, reason)
+    }
+
+    /// Read and unmask one frame, validating the header
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let mut header = [0u8; 2];
+        read_exact(&mut self.reader, &mut header)?;
+
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        if header[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, "reserved bits set"));
+        }
+        if !matches!(opcode, OP_CONTINUATION | OP_TEXT | OP_BINARY | OP_CLOSE | OP_PING | OP_PONG) {
+            return Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, "unknown opcode"));
+        }
+        // Clients must mask every frame
+        if header[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, "frame not masked"));
+        }
+
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut bytes = [0u8; 2];
+                read_exact(&mut self.reader, &mut bytes)?;
+                u16::from_be_bytes(bytes) as u64
+            }
+            127 => {
+                let mut bytes = [0u8; 8];
+                read_exact(&mut self.reader, &mut bytes)?;
+                u64::from_be_bytes(bytes)
+            }
+            len => len as u64,
+        };
+
+        let is_control = opcode & 0x8 != 0;
+        if is_control && (!fin || len > 125) {
+            return Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, "invalid control frame"));
+        }
+        if len > MAX_MESSAGE_BYTES as u64 {
+            return Err(WebSocketError::Protocol(CLOSE_TOO_BIG, "message too big"));
+        }
+
+        let mut mask = [0u8; 4];
+        read_exact(&mut self.reader, &mut mask)?;
+        let mut payload = vec![0u8; len as usize];
+        read_exact(&mut self.reader, &mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    /// Write one unfragmented, unmasked frame (servers never mask)
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => frame.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+
+        let connection = self.reader.get_mut();
+        connection.write_all(&frame)?;
+        connection.flush()?;
+        Ok(())
+    }
+}
+
+/// Like `read_exact`, but a connection closed by the peer is `Closed`
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), WebSocketError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => WebSocketError::Closed,
+        _ => WebSocketError::Io(e),
+    })
+}
+
+/// Status code of a close frame, if it has one. Codes a peer may not send
+/// (RFC 6455, section 7.4) and a lone status byte are protocol errors.
+fn close_code(payload: &[u8]) -> Result<Option<u16>, &'static str> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err("truncated close code"),
+        [high, low, ..] => match u16::from_be_bytes([*high, *low]) {
+            code if code < 1000 || matches!(code, 1005 | 1006 | 1015) => Err("invalid close code"),
+            code => Ok(Some(code)),
+        },
+    }
+}
@@ -0,0 +1,279 @@
+use std::cmp::Reverse;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use crate::config::Config;
+
+/// Log target of access log lines; they go to the access log, not the application log
+const ACCESS_TARGET: &str = "access";
+
+/// Text that must never appear in a log line and what to write instead (longest first)
+static REDACTIONS: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// Crate name as it appears in module paths
+const CRATE_NAME: &str = "regular_http_server";
+const CRATE_PREFIX: &str = "regular_http_server::";
+
+/// How application log lines are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// `2026-10-19T02:11:40.123+02:00 INFO  [http::server] message`
+    Plain,
+    /// One JSON object per line (time, level, target, message)
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(name: &str) -> Option<LogFormat> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "plain" => Some(LogFormat::Plain),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogFormat::Plain => "plain",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+/// Levels per module: `info` or `warn,http::server=debug` (the longest matching module wins)
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> Result<LogFilter, String> {
+        let mut filter = LogFilter {
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        };
+
+        for directive in spec.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            let invalid = || format!("Invalid LOG_LEVEL directive: {}", directive);
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = level.trim().parse().map_err(|_| invalid())?;
+                    let module = module.trim().trim_start_matches(CRATE_PREFIX).to_string();
+                    filter.modules.push((module, level));
+                }
+                None => filter.default = directive.parse().map_err(|_| invalid())?,
+            }
+        }
+
+        // Longest (most specific) modules first
+        filter.modules.sort_by_key(|(module, _)| Reverse(module.len()));
+        Ok(filter)
+    }
+
+    fn level_for(&self, module: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(prefix, _)| module == prefix || module.starts_with(&format!("{}::", prefix)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+}
+
+impl std::fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.default.as_str().to_lowercase())?;
+        for (module, level) in &self.modules {
+            write!(f, ",{}={}", module, level.as_str().to_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// Application log to stderr, access log to stdout or a file, with secrets removed from both
+struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    access: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if metadata.target() == ACCESS_TARGET {
+            return self.access.is_some();
+        }
+        metadata.level() <= self.filter.level_for(module_name(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = self.redact(record.args().to_string());
+
+        if record.target() == ACCESS_TARGET {
+            if let Some(access) = &self.access {
+                let mut out = access.lock().unwrap_or_else(|e| e.into_inner());
+                let _ = writeln!(out, "{}", message);
+                let _ = out.flush();
+            }
+            return;
+        }
+
+        let time = Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z");
+        let module = module_name(record.module_path().unwrap_or(record.target()));
+        let line = match self.format {
+            LogFormat::Plain => format!("{} {:<5} [{}] {}", time, record.level(), module, message),
+            LogFormat::Json => serde_json::json!({
+                "time": time.to_string(),
+                "level": record.level().as_str(),
+                "target": module,
+                "message": message,
+            })
+            .to_string(),
+        };
+        // One write per line, so lines of different threads don't interleave
+        let _ = io::stderr().lock().write_all(format!("{}\n", line).as_bytes());
+    }
+
+    fn flush(&self) {
+        if let Some(access) = &self.access {
+            let _ = access.lock().unwrap_or_else(|e| e.into_inner()).flush();
+        }
+    }
+}
+
+impl Logger {
+    fn redact(&self, mut message: String) -> String {
+        let redactions = REDACTIONS.read().unwrap_or_else(|e| e.into_inner());
+        for (secret, replacement) in redactions.iter() {
+            if message.contains(secret.as_str()) {
+                message = message.replace(secret.as_str(), replacement);
+            }
+        }
+        message
+    }
+}
+
+/// Install the logger; call once, before anything is logged
+pub fn init(config: &Config) -> Result<(), String> {
+    let access: Option<Box<dyn Write + Send>> = match config.access_log.as_str() {
+        "off" => None,
+        "stdout" => Some(Box::new(io::stdout())),
+        path => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open access log {}: {}", path, e))?;
+            Some(Box::new(file))
+        }
+    };
+
+    set_redactions(config);
+    let logger = Logger {
+        filter: config.log_level.clone(),
+        format: config.log_format,
+        access: access.map(Mutex::new),
+    };
+    // The log macros drop records above the max level before the logger sees them,
+    // and access lines are logged at info whatever LOG_LEVEL says
+    let max_level = match logger.access {
+        Some(_) => logger.filter.max_level().max(LevelFilter::Info),
+        None => logger.filter.max_level(),
+    };
+    log::set_max_level(max_level);
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| e.to_string())
+}
+
+/// Hide the feed URLs of `config` from now on; call on a reload before anything is logged.
+/// URLs of earlier configurations stay hidden, as a running sync may still report them.
+pub fn set_redactions(config: &Config) {
+    let mut redactions = REDACTIONS.write().unwrap_or_else(|e| e.into_inner());
+    // Feed URLs carry private tokens: hide the whole URL and, for error messages
+    // that quote it differently, its path and query
+    for url in &config.ics_urls {
+        let (origin, rest) = split_url(url);
+        if !rest.is_empty() && rest != "/" && !redactions.iter().any(|(secret, _)| secret == url) {
+            redactions.push((url.clone(), format!("{}/[redacted]", origin)));
+            redactions.push((rest.to_string(), "/[redacted]".to_string()));
+        }
+    }
+    // Full URLs before their paths
+    redactions.sort_by_key(|(secret, _)| Reverse(secret.len()));
+}
+
+/// One answered request for the access log
+pub struct AccessEntry<'a> {
+    pub remote_addr: Option<IpAddr>,
+    /// "GET /path?query HTTP/1.1", None if the request couldn't be parsed
+    pub request_line: Option<String>,
+    pub status: u16,
+    /// Body bytes sent
+    pub bytes: u64,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub duration: Duration,
+}
+
+/// Write an access log line: Combined Log Format plus the time taken in seconds
+pub fn access(entry: &AccessEntry) {
+    let quoted = |value: Option<&str>| match value {
+        Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "\"-\"".to_string(),
+    };
+
+    log::info!(
+        target: ACCESS_TARGET,
+        "{} - - [{}] {} {} {} {} {} {:.6}",
+        entry.remote_addr.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_string()),
+        Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+        quoted(entry.request_line.as_deref()),
+        entry.status,
+        if entry.bytes == 0 { "-".to_string() } else { entry.bytes.to_string() },
+        quoted(entry.referer),
+        quoted(entry.user_agent),
+        entry.duration.as_secs_f64()
+    );
+}
+
+/// Request target for the access log; share link tokens are replaced, as they grant access
+pub fn loggable_target(path: &str, query: &str) -> String {
+    let path = match path.strip_prefix("/s/") {
+        Some(rest) => match rest.split_once('/') {
+            Some((_, sub)) => format!("/s/[token]/{}", sub),
+            None => "/s/[token]".to_string(),
+        },
+        None => path.to_string(),
+    };
+    if query.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, query)
+    }
+}
+
+/// "https://example.com/a/b?c" -> ("https://example.com", "/a/b?c")
+fn split_url(url: &str) -> (&str, &str) {
+    let after_scheme = url.find("://").map(|i| i + 3).unwrap_or(0);
+    match url[after_scheme..].find(['/', '?']) {
+        Some(i) => url.split_at(after_scheme + i),
+        None => (url, ""),
+    }
+}
+
+/// Module path without the crate name ("http::server"; "main" for the crate root)
+fn module_name(path: &str) -> &str {
+    if path == CRATE_NAME {
+        return "main";
+    }
+    path.strip_prefix(CRATE_PREFIX).unwrap_or(path)
+}
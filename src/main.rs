@@ -1,29 +1,141 @@
+mod cli;
 mod config;
 mod calendar;
+mod health;
 mod http;
+mod logging;
+mod metrics;
+mod shared;
+mod signals;
 
-use calendar::{load_events, start_background_sync};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use health::Health;
+use calendar::{get_last_update, load_calendars, load_events, start_background_sync, EventStore, Snapshot, UpdateNotifier};
+use http::auth::Auth;
+use http::kiosk::Display;
+use http::login::LoginThrottle;
+use http::AppState;
+use log::{error, info, warn};
+use metrics::Metrics;
+use shared::Shared;
+use signals::{Reload, Shutdown};
+
+/// Configuration file, read at startup and on SIGHUP
+const CONFIG_FILE: &str = ".env";
+
+/// Exit code when the shutdown timeout cut off requests or the sync
+const EXIT_TIMEOUT: i32 = 1;
+
+/// Exit code when the server can't start (address in use, bad certificate)
+const EXIT_STARTUP: i32 = 2;
 
 fn main() {
-    println!("=== Starting Regular HTTP Server ===\n");
+    // Commands (hash-password, share ...) run instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // Load configuration
-    let config = config::Config::load(".env").expect("Failed to load .env");
-    println!("✓ Loaded config: {}", config);
+    // Load configuration (it also says how to log)
+    let config = config::Config::load(CONFIG_FILE).expect("Failed to load .env");
+    logging::init(&config).expect("Failed to set up logging");
+    info!("Starting Regular HTTP Server");
+    info!("Loaded config: {}", config);
 
     // Create data directory
     std::fs::create_dir_all("data").expect("Failed to create data directory");
 
     // LOAD EXISTING EVENTS FIRST (so server can start serving immediately)
-    println!("\n--- Loading cached events ---");
     let initial_events = load_events();
-    println!("✓ Loaded {} cached events from data/events.json\n", initial_events.len());
+    info!("Loaded {} cached events from data/events.json", initial_events.len());
+    // On a first start there's nothing to serve until the first sync
+    let health = Arc::new(Health::new());
+    if !initial_events.is_empty() {
+        health.mark_loaded();
+    }
+    // Requests are answered from memory; the sync replaces the events after each change
+    let events = Arc::new(EventStore::new(
+        Snapshot::new(initial_events, load_calendars(), config.tz()),
+        get_last_update(),
+    ));
 
     // START BACKGROUND SYNC (runs in separate thread)
-    println!("--- Starting background sync ---");
-    start_background_sync(config.clone());
+    // The sync notifies connected browsers when events change
+    let updates = Arc::new(UpdateNotifier::new());
+    // POST /api/refresh wakes the sync early
+    let refresh = Arc::new(UpdateNotifier::new());
+    let metrics = Arc::new(Metrics::new());
+    let shutdown = Arc::new(Shutdown::new());
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+
+    let auth = Auth::load(&config).expect("Failed to set up authentication");
+    if auth.is_enabled() {
+        info!("Authentication enabled");
+    }
+    let config = Arc::new(Shared::new(config));
+    let auth = Arc::new(Shared::new(auth));
+
+    let sync = start_background_sync(
+        Arc::clone(&config),
+        Arc::clone(&events),
+        Arc::clone(&updates),
+        Arc::clone(&refresh),
+        Arc::clone(&metrics),
+        Arc::clone(&health),
+        Arc::clone(&shutdown),
+    );
+
+    // SIGTERM/SIGINT shut down gracefully, SIGHUP reloads the configuration
+    let reload = Reload {
+        path: CONFIG_FILE.to_string(),
+        config: Arc::clone(&config),
+        auth: Arc::clone(&auth),
+        refresh: Arc::clone(&refresh),
+        updates: Arc::clone(&updates),
+    };
+    signals::listen(Arc::clone(&shutdown), reload).expect("Failed to set up signal handling");
+
+    // START HTTP SERVER (returns once a shutdown was requested and requests are drained)
+    let served = http::serve(
+        AppState {
+            config,
+            events,
+            updates,
+            display: Arc::new(Display::new()),
+            auth,
+            logins: Arc::new(LoginThrottle::new()),
+            refresh,
+            metrics,
+            health,
+        },
+        Arc::clone(&shutdown),
+    );
+    let drained = match served {
+        Ok(drained) => drained,
+        Err(e) => {
+            error!("{}", e);
+            log::logger().flush();
+            std::process::exit(EXIT_STARTUP);
+        }
+    };
+
+    // The sync gets what is left of the timeout; files being written are always finished
+    let deadline = Instant::now() + shutdown_timeout;
+    let sync_stopped = signals::join_until(sync, deadline);
+    if !sync_stopped {
+        warn!("Sync still running at the shutdown timeout, abandoning it");
+    }
+    let _writing = shutdown.write_guard();
+    log::logger().flush();
 
-    // START HTTP SERVER
-    println!("\n--- Starting HTTP server ---");
-    http::serve(&config.server_address);
+    if drained && sync_stopped {
+        info!("Shutdown complete");
+    } else {
+        std::process::exit(EXIT_TIMEOUT);
+    }
 }
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Request latency buckets in seconds
+const REQUEST_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Feed download buckets in seconds (the client gives up after 30)
+const FETCH_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Counters for `GET /metrics`, shared by the HTTP server and the background sync.
+///
+/// Feeds are labelled with their calendar id, never their URL (it contains a private token).
+pub struct Metrics {
+    /// (method, route, status) -> latency histogram (its count is the request count)
+    requests: Mutex<BTreeMap<(String, String, u16), Histogram>>,
+    active_connections: Arc<AtomicUsize>,
+    sync_runs: AtomicU64,
+    feeds: Mutex<BTreeMap<String, FeedStats>>,
+}
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket (not cumulative)
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// `name_bucket`, `name_sum` and `name_count` lines; `labels` is `key="value",...` or empty
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+struct FeedStats {
+    failures: u64,
+    fetch_duration: Histogram,
+    downloaded_bytes: u64,
+    /// Events parsed in the last successful fetch
+    events: usize,
+    last_success: Option<SystemTime>,
+}
+
+/// Counts an open connection until dropped
+pub struct ConnectionGuard {
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            sync_runs: AtomicU64::new(0),
+            feeds: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count a connection as active for as long as the guard lives
+    pub fn connection(&self) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            active: Arc::clone(&self.active_connections),
+        }
+    }
+
+    /// Record a handled request; `route` is the matched pattern, not the path (which is unbounded)
+    pub fn record_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        // Only known methods, so junk requests can't create new series
+        let method = match method {
+            "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "OPTIONS" | "PATCH" => method,
+            _ => "other",
+        };
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert_with(|| Histogram::new(&REQUEST_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Make a feed show up (with zero counts) before its first fetch
+    pub fn add_feed(&self, feed: &str) {
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        feeds.entry(feed.to_string()).or_insert_with(|| FeedStats {
+            failures: 0,
+            fetch_duration: Histogram::new(&FETCH_BUCKETS),
+            downloaded_bytes: 0,
+            events: 0,
+            last_success: None,
+        });
+    }
+
+    pub fn record_sync_run(&self) {
+        self.sync_runs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A feed was downloaded (`bytes`) and parsed into `events` events
+    pub fn record_fetch_success(&self, feed: &str, duration: Duration, bytes: usize, events: usize) {
+        self.update_feed(feed, |stats| {
+            stats.fetch_duration.observe(duration.as_secs_f64());
+            stats.downloaded_bytes += bytes as u64;
+            stats.events = events;
+            stats.last_success = Some(SystemTime::now());
+        });
+    }
+
+    pub fn record_fetch_failure(&self, feed: &str, duration: Duration) {
+        self.update_feed(feed, |stats| {
+            stats.fetch_duration.observe(duration.as_secs_f64());
+            stats.failures += 1;
+        });
+    }
+
+    fn update_feed(&self, feed: &str, update: impl FnOnce(&mut FeedStats)) {
+        self.add_feed(feed);
+        let mut feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(stats) = feeds.get_mut(feed) {
+            update(stats);
+        }
+    }
+
+    /// All metrics in the Prometheus text exposition format (version 0.0.4)
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        header(&mut out, "http_requests_total", "counter", "HTTP requests by method, route and status");
+        for ((method, route, status), histogram) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{{}}} {}",
+                request_labels(method, route, *status),
+                histogram.count
+            );
+        }
+        header(&mut out, "http_request_duration_seconds", "histogram", "Time from reading a request to sending its response");
+        for ((method, route, status), histogram) in requests.iter() {
+            histogram.render(&mut out, "http_request_duration_seconds", &request_labels(method, route, *status));
+        }
+        drop(requests);
+
+        header(&mut out, "http_active_connections", "gauge", "Open client connections, including streams and WebSockets");
+        let _ = writeln!(out, "http_active_connections {}", self.active_connections.load(Ordering::Relaxed));
+
+        header(&mut out, "calendar_sync_runs_total", "counter", "Completed background syncs");
+        let _ = writeln!(out, "calendar_sync_runs_total {}", self.sync_runs.load(Ordering::Relaxed));
+
+        let feeds = self.feeds.lock().unwrap_or_else(|e| e.into_inner());
+        let now = SystemTime::now();
+        let feed_label = |feed: &str| format!("feed=\"{}\"", escape_label(feed));
+
+        header(&mut out, "calendar_feed_failures_total", "counter", "Failed feed downloads");
+        for (feed, stats) in feeds.iter() {
+            let _ = writeln!(out, "calendar_feed_failures_total{{{}}} {}", feed_label(feed), stats.failures);
+        }
+        header(&mut out, "calendar_feed_fetch_duration_seconds", "histogram", "Feed download time");
+        for (feed, stats) in feeds.iter() {
+            stats.fetch_duration.render(&mut out, "calendar_feed_fetch_duration_seconds", &feed_label(feed));
+        }
+        header(&mut out, "calendar_feed_downloaded_bytes_total", "counter", "Bytes downloaded from feeds");
+        for (feed, stats) in feeds.iter() {
+            let _ = writeln!(out, "calendar_feed_downloaded_bytes_total{{{}}} {}", feed_label(feed), stats.downloaded_bytes);
+        }
+        header(&mut out, "calendar_events", "gauge", "Events parsed from each calendar in its last successful fetch");
+        for (feed, stats) in feeds.iter() {
+            let _ = writeln!(out, "calendar_events{{calendar=\"{}\"}} {}", escape_label(feed), stats.events);
+        }
+        header(
+            &mut out,
+            "calendar_feed_last_success_age_seconds",
+            "gauge",
+            "Seconds since the last successful fetch of each feed (+Inf before the first)",
+        );
+        for (feed, stats) in feeds.iter() {
+            let age = match stats.last_success {
+                Some(time) => now.duration_since(time).unwrap_or_default().as_secs_f64().to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(out, "calendar_feed_last_success_age_seconds{{{}}} {}", feed_label(feed), age);
+        }
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn request_labels(method: &str, route: &str, status: u16) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\",status=\"{}\"",
+        escape_label(method),
+        escape_label(route),
+        status
+    )
+}
+
+/// Label values escape backslash, double quote and newline
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,24 @@
+use std::sync::{Arc, RwLock};
+
+/// A value that can be replaced while running (configuration reloads).
+///
+/// Readers take a snapshot with `get` and keep using it even if it is replaced meanwhile.
+pub struct Shared<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Shared<T> {
+        Shared {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn replace(&self, value: T) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+    }
+}
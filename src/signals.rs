@@ -0,0 +1,158 @@
+use std::net::{SocketAddr, TcpStream};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use crate::calendar::UpdateNotifier;
+use crate::config::Config;
+use crate::http::auth::Auth;
+use crate::logging;
+use crate::shared::Shared;
+
+/// Exit code when a second SIGTERM/SIGINT cuts the drain short
+const EXIT_FORCED: i32 = 130;
+
+/// Coordinates a graceful shutdown between the signal handler, the HTTP server and the sync
+pub struct Shutdown {
+    requested: AtomicBool,
+    /// Where the server listens; connecting wakes up its blocking accept
+    listener: Mutex<Option<SocketAddr>>,
+    /// Held while state is written to disk, so the process never exits in the middle
+    writing: Mutex<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown {
+            requested: AtomicBool::new(false),
+            listener: Mutex::new(None),
+            writing: Mutex::new(()),
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Stop accepting connections; the server then drains and returns
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        if let Some(addr) = *self.listener.lock().unwrap_or_else(|e| e.into_inner()) {
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+        }
+    }
+
+    /// Register the listening address (a wildcard address is reached over loopback)
+    pub fn set_listener(&self, mut addr: SocketAddr) {
+        if addr.ip().is_unspecified() {
+            let loopback = if addr.is_ipv4() { [127, 0, 0, 1].into() } else { std::net::Ipv6Addr::LOCALHOST.into() };
+            addr.set_ip(loopback);
+        }
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(addr);
+    }
+
+    /// Hold the guard while writing files that must not be left half-written
+    pub fn write_guard(&self) -> MutexGuard<'_, ()> {
+        self.writing.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+/// What a SIGHUP reloads
+pub struct Reload {
+    pub path: String,
+    pub config: Arc<Shared<Config>>,
+    pub auth: Arc<Shared<Auth>>,
+    /// Woken for a sync with the new feeds, and to re-render connected screens
+    pub refresh: Arc<UpdateNotifier>,
+    pub updates: Arc<UpdateNotifier>,
+}
+
+/// Handle signals on a thread of their own: SIGTERM/SIGINT shut down gracefully
+/// (a second one exits right away), SIGHUP reloads the configuration
+pub fn listen(shutdown: Arc<Shutdown>, reload: Reload) -> Result<(), String> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP]).map_err(|e| format!("Failed to register signals: {}", e))?;
+
+    thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || {
+            for signal in signals.forever() {
+                match signal {
+                    SIGHUP => reload.run(),
+                    _ if shutdown.is_requested() => {
+                        warn!("Second shutdown signal, exiting now");
+                        let _guard = shutdown.write_guard();
+                        log::logger().flush();
+                        process::exit(EXIT_FORCED);
+                    }
+                    _ => {
+                        info!("Shutting down (signal {})", signal);
+                        shutdown.request();
+                        // Wake the sync so it stops instead of sleeping
+                        reload.refresh.notify();
+                    }
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start signal thread: {}", e))?;
+    Ok(())
+}
+
+impl Reload {
+    /// Load the configuration again; on errors the old one stays
+    fn run(&self) {
+        let config = match Config::load(&self.path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to reload {}, keeping the old configuration: {}", self.path, e);
+                return;
+            }
+        };
+        // New feeds must be hidden before anything mentions them
+        logging::set_redactions(&config);
+        let auth = match Auth::load(&config) {
+            Ok(auth) => auth,
+            Err(e) => {
+                error!("Failed to reload authentication, keeping the old configuration: {}", e);
+                return;
+            }
+        };
+
+        let old = self.config.get();
+        for key in old.restart_required(&config) {
+            warn!("{} changed; it takes effect after a restart", key);
+        }
+        // Events are stored in TIMEZONE, so a new one needs a sync too
+        let resync = old.ics_urls != config.ics_urls || old.timezone != config.timezone;
+
+        info!("Reloaded config: {}", config);
+        self.config.replace(config);
+        self.auth.replace(auth);
+        if resync {
+            self.refresh.notify();
+        }
+        // Names, colors and limits may have changed
+        self.updates.notify();
+    }
+}
+
+/// Wait up to `deadline` for a thread to finish; false if it is still running
+pub fn join_until(handle: thread::JoinHandle<()>, deadline: Instant) -> bool {
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let _ = handle.join();
+    true
+}